@@ -0,0 +1,7 @@
+// Searching for and downloading updates from Sony's servers, without any of the UI.
+// Used by the rusty-psn binary, but it doesn't depend on anything from it.
+
+#[macro_use] extern crate log;
+
+pub mod psn;
+pub mod utils;
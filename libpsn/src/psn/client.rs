@@ -0,0 +1,247 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+#[cfg(any(test, feature = "mock_client"))]
+use std::collections::HashMap;
+
+use reqwest::Url;
+use reqwest::cookie::Jar;
+
+use super::UpdateError;
+
+// Establishing a connection should be quick, while a whole request can take a while on Sony's servers.
+pub const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 30;
+pub const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 300;
+
+// Cookies are scoped to this domain, which covers all of the metadata hosts.
+const COOKIE_DOMAIN: &str = "playstation.net";
+
+// Host queried to check whether there's a network connection at all.
+const CONNECTIVITY_PROBE_URL: &str = "https://a0.ww.np.dl.playstation.net/";
+const CONNECTIVITY_PROBE_TIMEOUT_SECS: u64 = 10;
+
+// The requests made while searching for updates go through this trait,
+// so the parsing logic can be exercised without network access.
+pub trait PsnClient {
+    fn get_text(&self, url: &str) -> impl Future<Output = Result<String, UpdateError>> + Send;
+}
+
+// Tuning for the idle connections that are kept around to be reused. Unset values use reqwest's defaults.
+// Keeping connections to the same CDN host alive saves a handshake per request, and seems to make Sony drop transfers less.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PoolConfig {
+    pub max_idle_per_host: Option<usize>,
+    pub idle_timeout: Option<Duration>
+}
+
+impl PoolConfig {
+    pub fn apply(&self, mut builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        if let Some(max_idle) = self.max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+
+        if let Some(timeout) = self.idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+
+        builder
+    }
+}
+
+// Sends every request through the given proxy, eg. http://host:port or socks5://host:port.
+// Without one, reqwest picks up the HTTP_PROXY, HTTPS_PROXY and ALL_PROXY environment variables on its own.
+pub fn apply_proxy(builder: reqwest::ClientBuilder, proxy: Option<&str>) -> Result<reqwest::ClientBuilder, reqwest::Error> {
+    match proxy {
+        Some(url) => Ok(builder.proxy(reqwest::Proxy::all(url)?)),
+        None => Ok(builder)
+    }
+}
+
+// Checks a proxy URL up front, so a typo gets reported right away instead of as a failed request later on.
+pub fn validate_proxy_url(url: &str) -> Result<(), reqwest::Error> {
+    reqwest::Proxy::all(url).map(| _ | ())
+}
+
+#[derive(Clone, Default)]
+pub struct ClientConfig {
+    // Cookies in `name=value` form, sent along with the requests.
+    // Some region-locked or beta metadata endpoints need a session cookie to answer.
+    pub cookies: Vec<String>,
+    // How long to wait for a connection to be established.
+    pub connect_timeout: Option<Duration>,
+    // How long a whole request can take, from connecting to receiving the full response.
+    pub request_timeout: Option<Duration>,
+    pub pool: PoolConfig,
+    // See apply_proxy.
+    pub proxy: Option<String>,
+    // Pause before each request after the first, with some jitter added. Eases the load on Sony's servers
+    // during the PS4 manifest walk or when searching many serials, which seems to help with dropped requests.
+    pub request_delay: Duration
+}
+
+#[derive(Clone)]
+pub struct ReqwestClient {
+    client: reqwest::Client,
+    // Requests made through this client and its clones. reqwest doesn't say whether a connection was reused,
+    // but the time each request takes, logged along with this count, gives a good idea.
+    requests: Arc<AtomicUsize>,
+    request_delay: Duration
+}
+
+impl ReqwestClient {
+    pub fn new(config: &ClientConfig) -> Result<ReqwestClient, reqwest::Error> {
+        let mut builder = reqwest::ClientBuilder::default()
+            // Sony has funky certificates, so this needs to be enabled.
+            .danger_accept_invalid_certs(true)
+        ;
+
+        if let Some(timeout) = config.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        if let Some(timeout) = config.request_timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        builder = config.pool.apply(builder);
+        builder = apply_proxy(builder, config.proxy.as_deref())?;
+
+        let cookies = config.cookies.iter()
+            .map(| c | c.trim())
+            .filter(| c | !c.is_empty())
+            .collect::<Vec<&str>>()
+        ;
+
+        if !cookies.is_empty() {
+            let jar = Jar::default();
+
+            if let Ok(url) = Url::parse(&format!("https://{COOKIE_DOMAIN}")) {
+                for cookie in cookies {
+                    jar.add_cookie_str(&format!("{cookie}; Domain={COOKIE_DOMAIN}"), &url);
+                }
+            }
+
+            builder = builder.cookie_provider(Arc::new(jar));
+        }
+
+        let client = builder.build()?;
+
+        Ok(ReqwestClient { client, requests: Arc::new(AtomicUsize::new(0)), request_delay: config.request_delay })
+    }
+
+    // Any response from the metadata host counts, even an error status.
+    // Only failing to reach it at all means there's no usable connection.
+    pub async fn is_online(&self) -> bool {
+        let probe = self.client.head(CONNECTIVITY_PROBE_URL)
+            .timeout(Duration::from_secs(CONNECTIVITY_PROBE_TIMEOUT_SECS))
+            .send()
+            .await
+        ;
+
+        match probe {
+            Ok(_) => true,
+            Err(e) => {
+                warn!("Connectivity probe to {CONNECTIVITY_PROBE_URL} failed: {e}");
+                false
+            }
+        }
+    }
+}
+
+impl PsnClient for ReqwestClient {
+    async fn get_text(&self, url: &str) -> Result<String, UpdateError> {
+        let request_number = self.requests.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if request_number > 1 && !self.request_delay.is_zero() {
+            tokio::time::sleep(crate::utils::jittered(self.request_delay)).await;
+        }
+
+        let started_at = Instant::now();
+
+        let response = self.client.get(url).send().await.map_err(UpdateError::from_reqwest)?;
+        debug!("Request #{request_number} on the metadata client got a response in {} ms ({url})", started_at.elapsed().as_millis());
+
+        // Missing serials come back as client errors with a body that gets parsed later on,
+        // but a server error's body won't have anything useful in it.
+        if response.status().is_server_error() {
+            return Err(UpdateError::Reqwest(response.error_for_status().unwrap_err()));
+        }
+
+        response.text().await.map_err(UpdateError::from_reqwest)
+    }
+}
+
+// Caps the combined rate of every download that shares it. Tokens refill at the given rate, up to a second's worth,
+// and a transfer that takes more than there are waits for the difference once it has its data.
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    // Available tokens, which go negative when a chunk is bigger than what's left, and when they were last refilled.
+    state: Mutex<(f64, Instant)>
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> RateLimiter {
+        let bytes_per_sec = bytes_per_sec.max(1);
+        RateLimiter { bytes_per_sec, state: Mutex::new((bytes_per_sec as f64, Instant::now())) }
+    }
+
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec
+    }
+
+    pub async fn acquire(&self, bytes: u64) {
+        let rate = self.bytes_per_sec as f64;
+
+        let wait = {
+            let mut state = self.state.lock().unwrap_or_else(| e | e.into_inner());
+            let (tokens, last_refill) = &mut *state;
+            let now = Instant::now();
+
+            *tokens = (*tokens + now.duration_since(*last_refill).as_secs_f64() * rate).min(rate);
+            *last_refill = now;
+            *tokens -= bytes as f64;
+
+            if *tokens < 0.0 { Some(Duration::from_secs_f64(-*tokens / rate)) } else { None }
+        };
+
+        if let Some(wait) = wait {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(any(test, feature = "mock_client"))]
+#[derive(Clone)]
+pub struct MockResponse {
+    pub body: String
+}
+
+// Serves canned responses keyed by URL. Requests to any other URL fail.
+#[cfg(any(test, feature = "mock_client"))]
+#[derive(Clone, Default)]
+pub struct MockPsnClient {
+    responses: HashMap<String, MockResponse>
+}
+
+#[cfg(any(test, feature = "mock_client"))]
+impl MockPsnClient {
+    pub fn new() -> MockPsnClient {
+        MockPsnClient::default()
+    }
+
+    pub fn with_response<U: Into<String>, B: Into<String>>(mut self, url: U, body: B) -> MockPsnClient {
+        self.responses.insert(url.into(), MockResponse { body: body.into() });
+        self
+    }
+}
+
+#[cfg(any(test, feature = "mock_client"))]
+impl PsnClient for MockPsnClient {
+    async fn get_text(&self, url: &str) -> Result<String, UpdateError> {
+        match self.responses.get(url) {
+            Some(response) => Ok(response.body.clone()),
+            None => Err(UpdateError::UnhandledErrorResponse(format!("No mock response for {url}")))
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+use serde_json;
+
+use super::{PackageInfo, UpdateInfo};
+use crate::utils::HashAlgorithm;
+
+#[derive(Serialize, Deserialize)]
+struct Piece {
+    url: String,
+    #[serde(rename = "fileOffset")]
+    file_offset: u64,
+    #[serde(rename = "fileSize")]
+    file_size: u64,
+    #[serde(rename = "hashValue")]
+    hash_value: String
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    #[serde(rename = "originalFileSize")]
+    original_file_size: u64,
+    #[serde(rename = "packageDigest")]
+    package_digest: String,
+    #[serde(rename = "numberOfSplitFiles")]
+    number_of_split_files: u32,
+    pieces: Vec<Piece>,
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    NoPartsFound,
+    // The response wasn't JSON at all, usually an XML or HTML error page. Holds the start of the response.
+    NotJson(String),
+    JsonParsing(serde_json::Error),
+}
+
+// How much of a non-JSON response is kept for the error message.
+const NOT_JSON_SNIPPET_LENGTH: usize = 64;
+
+pub fn parse_manifest_response(response: String, parent_manifest_package: &PackageInfo, info: &mut UpdateInfo) -> Result<(), ParseError> {
+    // Manifests are JSON objects. Anything else is an error page, which would otherwise show up as a confusing JSON syntax error.
+    let trimmed_response = response.trim_start_matches('\u{feff}').trim_start();
+    if !trimmed_response.starts_with('{') {
+        let snippet = trimmed_response.chars().take(NOT_JSON_SNIPPET_LENGTH).collect::<String>();
+        return Err(ParseError::NotJson(snippet));
+    }
+
+    let manifest: Manifest = serde_json::from_str(response.as_ref()).map_err(ParseError::JsonParsing)?;
+    
+    if manifest.pieces.is_empty() {
+        return Err(ParseError::NoPartsFound)
+    }
+
+    // Part numbers are assigned from the order of the pieces, and both the file names and merge rely on them
+    // matching the offsets. Make sure that holds even if the manifest lists them in a different order.
+    let mut pieces = manifest.pieces;
+    if !pieces.windows(2).all(| w | w[0].file_offset < w[1].file_offset) {
+        warn!("Manifest pieces for version {} aren't sorted by their offsets, sorting them", parent_manifest_package.version);
+        pieces.sort_by_key(| piece | piece.file_offset);
+    }
+
+    // Older manifests leave it empty.
+    if !manifest.package_digest.is_empty() {
+        info.package_digests.insert(parent_manifest_package.version.to_owned(), manifest.package_digest.to_owned());
+    }
+
+    for (idx, piece) in pieces.iter().enumerate() {
+        let part_number = if manifest.number_of_split_files > 1 { Some(idx+1) } else { None };
+
+        // Manifests don't say which algorithm the hashes use, but the length gives it away.
+        let hash_algorithm = HashAlgorithm::from_hex_len(piece.hash_value.len()).unwrap_or_else(|| {
+            warn!("Unexpected hash length for part {} of version {}, assuming SHA-1", idx + 1, parent_manifest_package.version);
+            HashAlgorithm::Sha1
+        });
+
+        let part_package = PackageInfo{
+            version: parent_manifest_package.version.to_owned(),
+            sha1sum: piece.hash_value.to_owned(),
+            hash_algorithm,
+            url: piece.url.to_owned(),
+            size: piece.file_size, 
+            hash_whole_file: true,
+            offset: piece.file_offset,
+            manifest_url: String::new(),
+            changeinfo_url: parent_manifest_package.changeinfo_url.to_owned(),
+            required_firmware: parent_manifest_package.required_firmware.to_owned(),
+            part_number,
+            size_may_skip_suffix: false
+        };
+        info.packages.push(part_package);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::psn::utils::PlaformVariant;
+
+    #[test]
+    fn parse_non_json_manifest() {
+        let response = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error><Code>AccessDenied</Code><Message>Access Denied</Message></Error>"#;
+
+        let mut info = UpdateInfo::empty(PlaformVariant::PS4);
+        match parse_manifest_response(response.to_string(), &PackageInfo::empty(), &mut info) {
+            Err(ParseError::NotJson(snippet)) => assert!(snippet.starts_with("<?xml")),
+            Err(e) => panic!("Unexpected error for a non-JSON manifest: {:?}", e),
+            Ok(()) => panic!("Parsed a non-JSON manifest")
+        }
+
+        // Valid JSON that doesn't match the manifest's schema is still a parsing error.
+        match parse_manifest_response(String::from("{ \"pieces\": 5 }"), &PackageInfo::empty(), &mut info) {
+            Err(ParseError::JsonParsing(_)) => {}
+            Err(e) => panic!("Unexpected error for a mismatched manifest: {:?}", e),
+            Ok(()) => panic!("Parsed a mismatched manifest")
+        }
+    }
+
+    #[test]
+    fn parse_out_of_order_pieces() {
+        let response = r#"{
+            "originalFileSize": 300,
+            "packageDigest": "",
+            "numberOfSplitFiles": 3,
+            "pieces": [
+                { "url": "http://example.com/UP0000-CUSA00000_00-0000000000000000-A0101-V0100_1.pkg", "fileOffset": 100, "fileSize": 100, "hashValue": "b" },
+                { "url": "http://example.com/UP0000-CUSA00000_00-0000000000000000-A0101-V0100_0.pkg", "fileOffset": 0, "fileSize": 100, "hashValue": "a" },
+                { "url": "http://example.com/UP0000-CUSA00000_00-0000000000000000-A0101-V0100_2.pkg", "fileOffset": 200, "fileSize": 100, "hashValue": "c" }
+            ]
+        }"#;
+
+        let mut info = UpdateInfo::empty(PlaformVariant::PS4);
+        if let Err(e) = parse_manifest_response(response.to_string(), &PackageInfo::empty(), &mut info) {
+            panic!("Failed to parse manifest: {:?}", e);
+        }
+
+        let parts = info.packages.iter()
+            .map(| pkg | (pkg.part_number, pkg.offset, pkg.sha1sum.as_str()))
+            .collect::<Vec<_>>()
+        ;
+
+        assert_eq!(parts, vec![(Some(1), 0, "a"), (Some(2), 100, "b"), (Some(3), 200, "c")]);
+    }
+
+    #[test]
+    fn detect_hash_algorithm() {
+        let response = r#"{
+            "originalFileSize": 200,
+            "packageDigest": "",
+            "numberOfSplitFiles": 2,
+            "pieces": [
+                { "url": "http://example.com/UP0000-CUSA00000_00-0000000000000000-A0101-V0100_0.pkg", "fileOffset": 0, "fileSize": 100, "hashValue": "da39a3ee5e6b4b0d3255bfef95601890afd80709" },
+                { "url": "http://example.com/UP0000-CUSA00000_00-0000000000000000-A0101-V0100_1.pkg", "fileOffset": 100, "fileSize": 100, "hashValue": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855" }
+            ]
+        }"#;
+
+        let mut info = UpdateInfo::empty(PlaformVariant::PS4);
+        if let Err(e) = parse_manifest_response(response.to_string(), &PackageInfo::empty(), &mut info) {
+            panic!("Failed to parse manifest: {:?}", e);
+        }
+
+        let algorithms = info.packages.iter().map(| pkg | pkg.hash_algorithm).collect::<Vec<_>>();
+        assert_eq!(algorithms, vec![HashAlgorithm::Sha1, HashAlgorithm::Sha256]);
+    }
+}
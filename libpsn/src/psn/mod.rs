@@ -0,0 +1,2722 @@
+pub mod utils;
+pub mod client;
+mod parser;
+mod manifest_parser;
+
+use std::{collections::HashMap, fmt, io::SeekFrom, path::{Path, PathBuf}, str::FromStr, sync::Arc, time::Duration};
+
+use bytesize::ByteSize;
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::mpsc::Sender;
+use client::{apply_proxy, ClientConfig, PoolConfig, PsnClient, RateLimiter, ReqwestClient};
+use utils::{copy_pkg_file, get_platform_variant, move_file, quarantine_file, get_update_info_url, PlaformVariant};
+
+use crate::utils::{create_new_pkg_path, HashAlgorithm, Sha1Backend};
+
+#[derive(Debug)]
+pub enum DownloadStatus {
+    Progress(u64),
+    // Sent about once a second during the transfer, with the speed since the last one
+    // and the time left at that speed, if it's moving at all.
+    Speed { bytes_per_sec: u64, eta_secs: Option<u64> },
+    // The transfer failed, or the file came out wrong, and will be tried again after waiting for the given delay.
+    Retrying { attempt: u32, max: u32, delay: Duration },
+    // The download picks up from this many bytes into the file, which were already on disk.
+    Resuming(u64),
+    
+    Verifying,
+    DownloadSuccess,
+    DownloadFailure
+}
+
+#[derive(Debug)]
+pub enum SearchStatus {
+    // Sent while walking the manifests of PS4 updates, as (fetched, total).
+    ManifestProgress(usize, usize)
+}
+
+#[derive(Debug)]
+pub enum MergeStatus {
+    PartProgress(usize),
+    // Sent while a part is being copied, with how much of it has been written so far.
+    ByteProgress { part: usize, bytes_written: u64, part_size: u64 },
+    // Sent while the merged file is hashed against the manifest's packageDigest.
+    Verifying,
+    // Sent while the parts are deleted, once the merged files are in place.
+    CleaningUp,
+
+    MergeSuccess,
+    MergeFailure
+}
+
+#[derive(Debug)]
+pub enum MergeError {
+    FilepathMismatch(String),
+    FileMergeFailure,
+    PackagesUnmergable(String),
+    // Two parts of the same version claim the same part number, so they'd be copied over each other.
+    DuplicatePart(usize),
+    // A version's part numbers skip this one, the merged pkg would have a hole in it.
+    MissingPart(usize),
+    // The merged pkg doesn't match the packageDigest from the manifest. Both are hex strings.
+    MergedFileMismatch { expected: String, got: String },
+    InsufficientDiskSpace { required: u64, available: u64 }
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MergeError::FilepathMismatch(reason) => write!(f, "unexpected part file: {reason}"),
+            MergeError::FileMergeFailure => write!(f, "failed to write the merged file"),
+            MergeError::PackagesUnmergable(reason) => write!(f, "packages can't be merged: {reason}"),
+            MergeError::DuplicatePart(part) => write!(f, "part {part} is listed more than once"),
+            MergeError::MissingPart(part) => write!(f, "part {part} is missing"),
+            MergeError::MergedFileMismatch { expected, got } => write!(f, "merged file doesn't match the manifest's digest (expected {expected}, got {got})"),
+            MergeError::InsufficientDiskSpace { required, available } => {
+                write!(f, "not enough disk space ({} required, {} available)", ByteSize::b(*required), ByteSize::b(*available))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DownloadError {
+    // short_on_data is whether we received less data than expected.
+    // Sony's servers like to drop out before the transfer is actually completed.
+    // The hashes are hex strings, what Sony says the file should hash to and what it actually did.
+    HashMismatch { short_on_data: bool, expected: String, actual: String },
+    Tokio(tokio::io::Error),
+    Reqwest(reqwest::Error),
+    InsufficientDiskSpace { required: u64, available: u64 },
+    // The connection couldn't be established in time, or the transfer stalled for longer than the transfer timeout.
+    TimedOut,
+    // The receiving end of the status channel was dropped, so nobody is waiting on the download anymore.
+    Cancelled,
+    IoWithPath { error: tokio::io::Error, path: PathBuf }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DownloadError::HashMismatch { short_on_data, expected, actual } => {
+                if *short_on_data {
+                    write!(f, "hash mismatch on downloaded file (expected {expected}, got {actual}, received less data than expected)")
+                }
+                else {
+                    write!(f, "hash mismatch on downloaded file (expected {expected}, got {actual})")
+                }
+            }
+            DownloadError::Tokio(e) => write!(f, "io error: {e}"),
+            DownloadError::Reqwest(e) => write!(f, "request error: {e}"),
+            DownloadError::InsufficientDiskSpace { required, available } => {
+                write!(f, "not enough disk space ({} required, {} available)", ByteSize::b(*required), ByteSize::b(*available))
+            }
+            DownloadError::TimedOut => write!(f, "timed out waiting for Sony's servers"),
+            DownloadError::Cancelled => write!(f, "download was cancelled"),
+            DownloadError::IoWithPath { error, path } => write!(f, "io error at {}: {error}", path.display())
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DownloadError::Tokio(e) | DownloadError::IoWithPath { error: e, .. } => Some(e),
+            DownloadError::Reqwest(e) => Some(e),
+            DownloadError::HashMismatch { .. } | DownloadError::InsufficientDiskSpace { .. } | DownloadError::TimedOut | DownloadError::Cancelled => None
+        }
+    }
+}
+
+impl DownloadError {
+    // Timeouts get their own variant, so they can be told apart from other request errors.
+    fn from_reqwest(e: reqwest::Error) -> DownloadError {
+        if e.is_timeout() {
+            DownloadError::TimedOut
+        }
+        else {
+            DownloadError::Reqwest(e)
+        }
+    }
+}
+
+// Statuses are sent while the download is in progress. If the receiver is gone, the download gets cancelled.
+async fn send_download_status(tx: &Sender<DownloadStatus>, status: DownloadStatus) -> Result<(), DownloadError> {
+    tx.send(status).await.map_err(| _ | DownloadError::Cancelled)
+}
+
+async fn send_search_status(tx: &Option<Sender<SearchStatus>>, status: SearchStatus) -> Result<(), UpdateError> {
+    match tx {
+        Some(tx) => tx.send(status).await.map_err(| _ | UpdateError::Cancelled),
+        None => Ok(())
+    }
+}
+
+// Flushes a verified download to disk and gives it its final name.
+async fn finish_pkg_file(pkg_file: tokio::fs::File, temp_path: &Path, path: &Path) -> Result<(), DownloadError> {
+    pkg_file.sync_all().await.map_err(| error | DownloadError::IoWithPath { error, path: temp_path.to_path_buf() })?;
+
+    // The file has to be closed before it can be renamed on Windows.
+    drop(pkg_file);
+
+    move_file(temp_path, path).await.map_err(| error | DownloadError::IoWithPath { error, path: path.to_path_buf() })
+}
+
+// How much of a download the file at the path holds, see utils::data_length.
+async fn received_length(path: &Path) -> Result<u64, DownloadError> {
+    let mut file = tokio::fs::File::open(path).await.map_err(| error | DownloadError::IoWithPath { error, path: path.to_path_buf() })?;
+    crate::utils::data_length(&mut file).await.map_err(| error | DownloadError::IoWithPath { error, path: path.to_path_buf() })
+}
+
+// Sony's CDN drops transfers often enough that giving up on the first one isn't reasonable.
+pub const DEFAULT_DOWNLOAD_RETRIES: u32 = 3;
+// The wait before each retry doubles, up to this.
+const MAX_RETRY_DELAY_SECS: u64 = 60;
+
+// Files take a bit more than their size on disk, and filling a disk up to the last byte isn't a good idea either.
+const DISK_SPACE_BUFFER_PERCENT: u64 = 5;
+
+fn with_space_buffer(size: u64) -> u64 {
+    size.saturating_add(size / 100 * DISK_SPACE_BUFFER_PERCENT)
+}
+
+fn retry_delay(attempt: u32) -> Duration {
+    Duration::from_secs(2u64.saturating_pow(attempt).min(MAX_RETRY_DELAY_SECS))
+}
+
+const SPEED_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Default)]
+pub struct DownloadConfig {
+    // Free space (in bytes) that has to remain on the disk once a download completes.
+    pub min_free_space: u64,
+    // Assume existing files with the expected size are complete instead of hashing them.
+    // Freshly downloaded data is always verified.
+    pub skip_verify_existing: bool,
+    // How long to wait for the connection to the CDN to be established.
+    pub connect_timeout: Option<Duration>,
+    // How long to wait for more data during the transfer. Unlike the metadata requests this doesn't cap
+    // the total time, since big pkgs can take a long while to download.
+    pub transfer_timeout: Option<Duration>,
+    pub sha1_backend: Sha1Backend,
+    pub pool: PoolConfig,
+    // See client::apply_proxy.
+    pub proxy: Option<String>,
+    // How many more times a download is attempted. Dropped or truncated transfers pick up where they stopped,
+    // files that fail verification are downloaded again from the start.
+    pub max_retries: u32,
+    // Move files that fail verification after being downloaded to a failed/ folder, instead of leaving them in place.
+    pub quarantine_failed: bool,
+    // Keep folder names valid on Windows even on other OSes, see utils::create_new_pkg_path.
+    pub strict_file_names: bool,
+    // Bandwidth cap. Downloads holding the same limiter split it between them.
+    pub rate_limit: Option<Arc<RateLimiter>>,
+    // What checking the file at the download's path found right before starting it (see pre_check_package),
+    // so it isn't hashed a second time. Only meant for a single download.
+    pub existing_status: Option<PkgFileStatus>,
+    // Client for the transfers, built from the settings above. Sharing one between downloads keeps connections
+    // to the CDN alive across parts and retries. When unset, each download builds its own.
+    pub client: Option<reqwest::Client>
+}
+
+impl DownloadConfig {
+    pub fn build_client(&self) -> Result<reqwest::Client, reqwest::Error> {
+        let mut client_builder = reqwest::ClientBuilder::default()
+            // Sony has funky certificates, so this needs to be enabled.
+            .danger_accept_invalid_certs(true)
+            // Some mirrors serve pkgs gzip-encoded. The file on disk (and its hash) must be the decoded pkg.
+            .gzip(true)
+        ;
+
+        if let Some(timeout) = self.connect_timeout {
+            client_builder = client_builder.connect_timeout(timeout);
+        }
+
+        client_builder = self.pool.apply(client_builder);
+        apply_proxy(client_builder, self.proxy.as_deref())?.build()
+    }
+
+    // Returns this config with a client that every download using it (or a clone of it) will share.
+    pub fn with_shared_client(mut self) -> Result<DownloadConfig, reqwest::Error> {
+        if self.client.is_none() {
+            self.client = Some(self.build_client()?);
+        }
+
+        Ok(self)
+    }
+}
+
+#[derive(Debug)]
+pub enum UpdateError {
+    InvalidSerial,
+    NoUpdatesAvailable,
+    UnhandledErrorResponse(String),
+    Reqwest(reqwest::Error),
+    XmlParsing(quick_xml::Error),
+    ManifestParsing(serde_json::Error),
+    // The manifest endpoint answered with something that isn't JSON. Holds the start of the response.
+    ManifestNotJson(String),
+    // Sony's servers didn't answer within the connect or request timeout.
+    TimedOut,
+    // The receiving end of the status channel was dropped while fetching manifests.
+    Cancelled
+}
+
+impl UpdateError {
+    pub(crate) fn from_reqwest(e: reqwest::Error) -> UpdateError {
+        if e.is_timeout() {
+            UpdateError::TimedOut
+        }
+        else {
+            UpdateError::Reqwest(e)
+        }
+    }
+
+    // Whether trying again might work. Sony's servers time out and return garbled responses every so often,
+    // but a serial that doesn't exist won't start existing a few seconds later.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            UpdateError::Reqwest(e) => {
+                e.is_timeout() || e.is_connect() || e.status().map(| s | s.is_server_error()).unwrap_or(false)
+            }
+            UpdateError::XmlParsing(_) | UpdateError::ManifestParsing(_) | UpdateError::ManifestNotJson(_) | UpdateError::TimedOut => true,
+            UpdateError::InvalidSerial | UpdateError::NoUpdatesAvailable | UpdateError::UnhandledErrorResponse(_) | UpdateError::Cancelled => false
+        }
+    }
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UpdateError::InvalidSerial => write!(f, "the serial didn't give any results"),
+            UpdateError::NoUpdatesAvailable => write!(f, "the serial doesn't have any available updates"),
+            UpdateError::UnhandledErrorResponse(e) => write!(f, "unexpected error response from PSN: {e}"),
+            UpdateError::Reqwest(e) => write!(f, "request error: {e}"),
+            UpdateError::XmlParsing(e) => write!(f, "failed to parse the response: {e}"),
+            UpdateError::ManifestParsing(e) => write!(f, "failed to parse a manifest: {e}"),
+            UpdateError::ManifestNotJson(response) => write!(f, "manifest response wasn't JSON: {response}"),
+            UpdateError::TimedOut => write!(f, "request timed out"),
+            UpdateError::Cancelled => write!(f, "search was cancelled")
+        }
+    }
+}
+
+impl std::error::Error for UpdateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            UpdateError::Reqwest(e) => Some(e),
+            UpdateError::XmlParsing(e) => Some(e),
+            UpdateError::ManifestParsing(e) => Some(e),
+            UpdateError::InvalidSerial | UpdateError::NoUpdatesAvailable | UpdateError::UnhandledErrorResponse(_) | UpdateError::ManifestNotJson(_) | UpdateError::TimedOut | UpdateError::Cancelled => None
+        }
+    }
+}
+
+// Result of checking a folder of pkgs against an update's info. Packages are listed by their id.
+#[derive(Debug, Default)]
+pub struct FolderVerification {
+    pub verified: Vec<String>,
+    pub mismatched: Vec<String>,
+    // Packages without a matching file in the folder.
+    pub missing: Vec<String>,
+    // Files in the folder that don't belong to any of the packages.
+    pub unmatched_files: Vec<PathBuf>
+}
+
+// Overview of an update, for when the details of each package aren't needed.
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub title_id: String,
+    // Empty if the update info doesn't include one.
+    pub title: String,
+    pub platform_variant: PlaformVariant,
+    pub package_count: usize,
+    pub total_size: u64,
+    pub latest_version: Option<String>
+}
+
+impl fmt::Display for SearchResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}", self.platform_variant, self.title_id)?;
+
+        if !self.title.is_empty() {
+            write!(f, " - {}", self.title)?;
+        }
+
+        write!(f, " ({} update(s) - {} total)", self.package_count, ByteSize::b(self.total_size))
+    }
+}
+
+// State of a package's file on disk.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PkgFileStatus {
+    Missing,
+    // Smaller than the package, likely an interrupted download.
+    Partial { size: u64 },
+    Verified,
+    Mismatched
+}
+
+// A list of updates saved to disk, to check on or download later without searching again.
+#[derive(Debug)]
+pub enum PlanError {
+    Io(std::io::Error),
+    Json(serde_json::Error)
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlanError::Io(e) => write!(f, "io error: {e}"),
+            PlanError::Json(e) => write!(f, "invalid plan: {e}")
+        }
+    }
+}
+
+// Checks the file a package would be downloaded to, before starting the download.
+// Returns where the file is, and whether it's complete, partially downloaded, wrong or not there at all.
+pub async fn pre_check_package(pkg: &PackageInfo, download_path: &Path, serial: &str, title: &str, config: &DownloadConfig) -> Result<(PathBuf, PkgFileStatus), DownloadError> {
+    let path = pkg.file_path(download_path, serial, title, config.strict_file_names);
+
+    let status = pkg.check_file(&path, config.sha1_backend).await?;
+    Ok((path, status))
+}
+
+pub fn save_plan(path: &Path, updates: &[UpdateInfo]) -> Result<(), PlanError> {
+    write_json(path, updates)
+}
+
+pub fn load_plan(path: &Path) -> Result<Vec<UpdateInfo>, PlanError> {
+    read_json(path)
+}
+
+// Plans and manifests are both pretty-printed JSON, so they're written and read the same way.
+fn write_json<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<(), PlanError> {
+    let json = serde_json::to_string_pretty(value).map_err(PlanError::Json)?;
+    std::fs::write(path, json).map_err(PlanError::Io)
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, PlanError> {
+    let json = std::fs::read_to_string(path).map_err(PlanError::Io)?;
+    serde_json::from_str(&json).map_err(PlanError::Json)
+}
+
+// A flatter take on a plan, listing only what's needed to download each package.
+// Meant to be read by people and other tools, or shared, unlike plans which mirror UpdateInfo.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ManifestTitle {
+    pub title_id: String,
+    pub title: String,
+    pub platform: PlaformVariant,
+    pub packages: Vec<ManifestPackage>,
+    // Same as UpdateInfo::package_digests, so merges of a manifest's parts can still be checked.
+    #[serde(default)]
+    pub package_digests: HashMap<String, String>
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ManifestPackage {
+    pub url: String,
+    pub version: String,
+    pub sha1sum: String,
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    #[serde(default)]
+    pub hash_whole_file: bool,
+    pub size: u64,
+    pub part_number: Option<usize>,
+    #[serde(default)]
+    pub offset: u64
+}
+
+impl From<&UpdateInfo> for ManifestTitle {
+    fn from(update: &UpdateInfo) -> ManifestTitle {
+        ManifestTitle {
+            title_id: update.title_id.clone(),
+            title: update.title(),
+            platform: update.platform_variant,
+            packages: update.packages.iter()
+                .map(| pkg | ManifestPackage {
+                    url: pkg.url.clone(),
+                    version: pkg.version.clone(),
+                    sha1sum: pkg.sha1sum.clone(),
+                    hash_algorithm: pkg.hash_algorithm,
+                    hash_whole_file: pkg.hash_whole_file,
+                    size: pkg.size,
+                    part_number: pkg.part_number,
+                    offset: pkg.offset
+                })
+                .collect(),
+            package_digests: update.package_digests.clone()
+        }
+    }
+}
+
+// Manifests don't have everything a search gives, like the patch notes. Those are left empty.
+impl From<ManifestTitle> for UpdateInfo {
+    fn from(title: ManifestTitle) -> UpdateInfo {
+        let mut info = UpdateInfo::empty(title.platform);
+        info.title_id = title.title_id;
+        info.titles.push(title.title);
+        info.package_digests = title.package_digests;
+
+        for package in title.packages {
+            let mut pkg = PackageInfo::empty();
+            pkg.url = package.url;
+            pkg.version = package.version;
+            pkg.sha1sum = package.sha1sum;
+            pkg.hash_algorithm = package.hash_algorithm;
+            pkg.hash_whole_file = package.hash_whole_file;
+            pkg.size = package.size;
+            pkg.part_number = package.part_number;
+            pkg.offset = package.offset;
+            pkg.size_may_skip_suffix = title.platform == PlaformVariant::PS3;
+
+            info.packages.push(pkg);
+        }
+
+        info
+    }
+}
+
+pub fn save_manifest(path: &Path, updates: &[UpdateInfo]) -> Result<(), PlanError> {
+    let manifest: Vec<ManifestTitle> = updates.iter().map(ManifestTitle::from).collect();
+    write_json(path, &manifest)
+}
+
+pub fn load_manifest(path: &Path) -> Result<Vec<UpdateInfo>, PlanError> {
+    let manifest: Vec<ManifestTitle> = read_json(path)?;
+    Ok(manifest.into_iter().map(UpdateInfo::from).collect())
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct UpdateInfo {
+    pub title_id: String,
+    pub tag_name: String,
+
+    pub titles: Vec<String>,
+    pub packages: Vec<PackageInfo>,
+    pub platform_variant: PlaformVariant,
+    // Digest of each version's whole pkg, keyed by version, for split PS4 updates. Used to check merged pkgs.
+    #[serde(default)]
+    pub package_digests: HashMap<String, String>,
+}
+
+impl UpdateInfo {
+    fn empty(platform_variant: PlaformVariant) -> UpdateInfo {
+        UpdateInfo {
+            title_id: String::new(),
+            tag_name: String::new(),
+
+            titles: Vec::new(),
+            packages: Vec::new(),
+            platform_variant,
+            package_digests: HashMap::new(),
+        }
+    }
+
+    pub fn matches_platform(&self, variant: PlaformVariant) -> bool {
+        self.platform_variant == variant
+    }
+
+    pub fn title(&self) -> String {
+        if let Some(title) = self.titles.get(0) {
+            title.clone()
+        }
+        else {
+            String::new()
+        }
+    }
+
+    pub fn summary(&self) -> SearchResult {
+        SearchResult {
+            title_id: self.title_id.clone(),
+            title: self.title(),
+            platform_variant: self.platform_variant,
+            package_count: self.packages.len(),
+            total_size: self.packages.iter().map(| pkg | pkg.size).sum(),
+            // Versions are zero-padded (eg. 01.05), so they sort correctly as strings.
+            latest_version: self.packages.iter().map(| pkg | pkg.version.clone()).max()
+        }
+    }
+
+    // The client is the caller's, so searches follow its proxy and timeouts, and share its connections.
+    pub async fn get_info(client: &ReqwestClient, title_id: String) -> Result<UpdateInfo, UpdateError> {
+        UpdateInfo::get_info_with(client, title_id).await
+    }
+
+    pub async fn get_info_with<C: PsnClient>(client: &C, title_id: String) -> Result<UpdateInfo, UpdateError> {
+        UpdateInfo::get_info_with_progress(client, title_id, None).await
+    }
+
+    // Same as get_info_with, but reports the progress of the PS4 manifest walk through tx.
+    // Dropping the receiver cancels the search before the next manifest is fetched.
+    pub async fn get_info_with_progress<C: PsnClient>(client: &C, title_id: String, tx: Option<Sender<SearchStatus>>) -> Result<UpdateInfo, UpdateError> {
+        let title_id = parse_title_id(&title_id);
+        let platform_variant = match get_platform_variant(&title_id) {
+            Some(variant) => variant,
+            None => return Err(UpdateError::InvalidSerial)
+        };
+        let url = match get_update_info_url(&title_id, platform_variant) {
+            Ok(url) => url,
+            Err(err) => return Err(err)
+        };
+        info!("Querying for updates for serial: {}", title_id);
+    
+        let response_txt = client.get_text(&url).await?;
+
+        // Some responses start with a UTF-8 BOM, which the XML reader would take as text before the first element.
+        let response_txt = match response_txt.strip_prefix('\u{feff}') {
+            Some(stripped) => stripped.to_string(),
+            None => response_txt
+        };
+
+        if response_txt.is_empty() {
+            return Err(UpdateError::NoUpdatesAvailable)
+        }
+
+        if response_txt.contains("Not found") {
+            return Err(UpdateError::InvalidSerial)
+        }
+
+        let mut info = UpdateInfo::empty(platform_variant);
+        match parser::parse_response(response_txt, &mut info) {
+            Ok(()) => {
+                if info.title_id.is_empty() || info.packages.is_empty() {
+                    return Err(UpdateError::NoUpdatesAvailable)
+                }
+
+                // This abomination comes courtesy of BCUS98233.
+                // For some ungodly reason, the title has a newline (/n), which of course causes issues
+                // both when displaying the title and when trying to create a folder to put the files in.
+                let titles = &info.titles;
+                info.titles = titles
+                    .into_iter()
+                    .map(| title | title.replace("\n", " "))
+                    .collect()
+                ;
+
+                if platform_variant == PlaformVariant::PS3 {
+                    for pkg in info.packages.iter_mut() {
+                        pkg.size_may_skip_suffix = true;
+                    }
+                }
+            }
+            Err(e) => {
+                match e {
+                    parser::ParseError::ErrorCode(reason) => {
+                        if reason == "NoSuchKey" {
+                            return Err(UpdateError::InvalidSerial);
+                        }
+
+                        return Err(UpdateError::UnhandledErrorResponse(reason));
+                    },
+                    parser::ParseError::XmlParsing(reason) => return Err(UpdateError::XmlParsing(reason))
+                }
+            }
+        }
+
+        if platform_variant != PlaformVariant::PS4 {
+            return Ok(info)
+        }
+
+        let mut parent_manifest_packages = info.packages;
+        info.packages = Vec::new(); // previously fetched manifest packages are moved out of packages list and a new list of part packages will be filled-in instead
+
+        let manifest_count = parent_manifest_packages.len();
+        send_search_status(&tx, SearchStatus::ManifestProgress(0, manifest_count)).await?;
+
+        for (i, package) in parent_manifest_packages.drain(..).enumerate() {
+            let manifest_response_txt = client.get_text(&package.manifest_url).await?;
+            send_search_status(&tx, SearchStatus::ManifestProgress(i + 1, manifest_count)).await?;
+
+            match manifest_parser::parse_manifest_response(manifest_response_txt, &package, &mut info) {
+                Ok(()) => {}
+                Err(e) => { 
+                    match e {
+                        manifest_parser::ParseError::NoPartsFound => return Err(UpdateError::NoUpdatesAvailable),
+                        manifest_parser::ParseError::NotJson(response) => return Err(UpdateError::ManifestNotJson(response)),
+                        manifest_parser::ParseError::JsonParsing(reason) => return Err(UpdateError::ManifestParsing(reason)),
+                    };
+                }
+            }
+        }
+
+        Ok(info)
+    }
+
+    // Verifies pkgs that might have been downloaded by other means against the hashes Sony provides.
+    // Files are matched to packages by their file name.
+    pub async fn verify_folder(&self, folder: &Path, backend: Sha1Backend) -> Result<FolderVerification, DownloadError> {
+        let mut result = FolderVerification::default();
+        let mut matched_files = Vec::new();
+
+        for pkg in self.packages.iter() {
+            let file_name = match pkg.file_name() {
+                Some(name) => name,
+                None => {
+                    warn!("Couldn't get a file name for {} {}, can't look for it", self.title_id, pkg.id());
+                    result.missing.push(pkg.id());
+                    continue;
+                }
+            };
+
+            let path = folder.join(&file_name);
+            if !path.is_file() {
+                result.missing.push(pkg.id());
+                continue;
+            }
+
+            info!("Verifying {:?} for {} {}", path, self.title_id, pkg.id());
+
+            match pkg.check_file(&path, backend).await? {
+                PkgFileStatus::Verified => result.verified.push(pkg.id()),
+                // Can't happen after the check above, unless the file was removed in the meantime.
+                PkgFileStatus::Missing => result.missing.push(pkg.id()),
+                // An incomplete copy isn't the file Sony serves either.
+                PkgFileStatus::Partial { .. } | PkgFileStatus::Mismatched => result.mismatched.push(pkg.id())
+            }
+
+            matched_files.push(file_name);
+        }
+
+        let mut entries = tokio::fs::read_dir(folder).await
+            .map_err(| error | DownloadError::IoWithPath { error, path: folder.to_path_buf() })?
+        ;
+
+        while let Some(entry) = entries.next_entry().await.map_err(| error | DownloadError::IoWithPath { error, path: folder.to_path_buf() })? {
+            let path = entry.path();
+            let file_name = entry.file_name().to_string_lossy().to_string();
+
+            if path.is_file() && !matched_files.contains(&file_name) {
+                result.unmatched_files.push(path);
+            }
+        }
+
+        Ok(result)
+    }
+
+    // Every version's parts have to be numbered 1 to n, without repeats or gaps.
+    // Parsing numbers them that way, but info put together from different fetches might not be.
+    fn validate_parts(&self) -> Result<(), MergeError> {
+        let mut versions: Vec<&str> = self.packages.iter().map(| pkg | pkg.version.as_str()).collect();
+        versions.dedup();
+
+        for version in versions {
+            let mut part_numbers: Vec<usize> = self.packages.iter()
+                .filter(| pkg | pkg.version == version)
+                .filter_map(| pkg | pkg.part_number)
+                .collect()
+            ;
+
+            part_numbers.sort_unstable();
+
+            if let Some(pair) = part_numbers.windows(2).find(| pair | pair[0] == pair[1]) {
+                return Err(MergeError::DuplicatePart(pair[0]));
+            }
+
+            if let Some((idx, _)) = part_numbers.iter().enumerate().find(| (idx, number) | **number != idx + 1) {
+                return Err(MergeError::MissingPart(idx + 1));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Checked before the merged file is moved into place, so a bad merge doesn't end up next to the parts.
+    // Returns whether the file could actually be checked, which isn't the case with digests in an unknown format.
+    async fn verify_merged_file(&self, path: &Path, version: &str, expected: &str) -> Result<bool, MergeError> {
+        let algorithm = match HashAlgorithm::from_hex_len(expected.len()) {
+            Some(algorithm) => algorithm,
+            None => {
+                warn!("Unexpected package digest for {} {version} ({expected}), not checking the merged file", self.title_id);
+                return Ok(false);
+            }
+        };
+
+        let expected_bytes = match hex::decode(expected) {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                warn!("Unexpected package digest for {} {version} ({expected}), not checking the merged file", self.title_id);
+                return Ok(false);
+            }
+        };
+
+        let mut file = match tokio::fs::File::open(path).await {
+            Ok(file) => file,
+            Err(e) => {
+                error!("could not open merged file {:?} to check it: {e}", path);
+                return Err(MergeError::FileMergeFailure);
+            }
+        };
+
+        let got = match crate::utils::compute_file_hash(&mut file, true, algorithm, Sha1Backend::default()).await {
+            Ok(got) => got,
+            Err(e) => {
+                error!("could not hash merged file {:?}: {e}", path);
+                return Err(MergeError::FileMergeFailure);
+            }
+        };
+
+        if got != expected_bytes {
+            let got = hex::encode(got);
+
+            error!("Merged file for {} {version} doesn't match the manifest's digest (expected {expected}, got {got})", self.title_id);
+            return Err(MergeError::MergedFileMismatch { expected: expected.to_string(), got });
+        }
+
+        info!("Merged file for {} {version} matches the manifest's digest", self.title_id);
+        Ok(true)
+    }
+
+    // Parts are merged into a temporary file in scratch_path (or the title's folder if there's none),
+    // which only gets moved to its final location once every part has been copied.
+    // With delete_parts, the parts are removed once every merged file has been checked and moved into place.
+    // Parts of a version without a usable digest are always kept, there's nothing proving the merged file is right.
+    pub async fn merge_parts(&self, tx: Sender<MergeStatus>, download_path: &PathBuf, strict_names: bool, name_template: &str, scratch_path: Option<&Path>, delete_parts: bool) -> Result<(), MergeError> {
+        if !self.packages.iter().all(|pkg| pkg.part_number.is_some()) {
+            return Err(MergeError::PackagesUnmergable(String::from("some packages for the update are not a partial package")));
+        }
+
+        self.validate_parts()?;
+
+        let multiple_versions = self.packages.iter().any(| pkg | pkg.version != self.packages[0].version);
+
+        let mut packages_sorted_by_part_number = self.packages.clone();
+        packages_sorted_by_part_number.sort_by_key(|pkg| pkg.part_number.unwrap());
+        let package_download_path = create_new_pkg_path(&download_path, &self.title_id, &self.title(), strict_names);
+
+        let scratch_path = scratch_path.map(| p | p.to_path_buf()).unwrap_or_else(|| package_download_path.clone());
+        // Temp path, final path and version of each merged file.
+        let mut merged_files: Vec<(PathBuf, PathBuf, String)> = Vec::new();
+
+        if let Err(e) = tokio::fs::create_dir_all(&scratch_path).await {
+            error!("could not create scratch folder {:?}: {e}", scratch_path);
+            return Err(MergeError::FileMergeFailure);
+        }
+
+        // The parts add up to the manifest's originalFileSize, and the merged files are written in full before the parts go away.
+        let required = with_space_buffer(self.packages.iter().map(| pkg | pkg.size).sum());
+
+        match crate::utils::available_space(&scratch_path) {
+            Ok(available) => {
+                if available < required {
+                    error!("Not enough disk space to merge {}: {required} bytes required, {available} bytes available", self.title_id);
+                    return Err(MergeError::InsufficientDiskSpace { required, available });
+                }
+            }
+            Err(e) => warn!("Failed to check available disk space for {:?}: {e}", scratch_path)
+        }
+
+        info!("Starting merge for {}", self.title());
+
+        for package in self.packages.iter() {
+            let file_name = match package.file_name() {
+                Some(name) => name,
+                None => return Err(MergeError::FilepathMismatch(String::from("could not deduce filename from a package url")))
+            };
+
+            let part_number = package.part_number.unwrap();
+            let expected_end_of_file_name = format!("_{}.pkg", part_number - 1);
+            if !file_name.ends_with(&expected_end_of_file_name) {
+                return Err(MergeError::FilepathMismatch(String::from("package name does not end with expected index and extension")))
+            }
+
+            let original_name = file_name.replace(&expected_end_of_file_name, "");
+            let merged_file_name = render_merged_file_name(name_template, &self.title_id, &package.version, &original_name, multiple_versions);
+            let mut merged_path = package_download_path.clone();
+            merged_path.push(&merged_file_name);
+
+            let temp_path = match merged_files.iter().find(| (_, target, _) | *target == merged_path) {
+                Some((temp, _, _)) => temp.clone(),
+                None => {
+                    let temp_path = scratch_path.join(format!("{merged_file_name}.tmp"));
+
+                    // Leftovers from an interrupted merge would otherwise end up in the new file.
+                    let _ = tokio::fs::remove_file(&temp_path).await;
+                    merged_files.push((temp_path.clone(), merged_path.clone(), package.version.clone()));
+                    temp_path
+                }
+            };
+
+            let mut package_path = package_download_path.clone();
+            package_path.push(&file_name);
+            let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(10);
+            let forward_progress = async {
+                // Ends once copy_pkg_file is done and drops its sender. Updates that don't fit are
+                // dropped, the next one makes up for it and the copy shouldn't wait on whoever's listening.
+                while let Some(bytes_written) = progress_rx.recv().await {
+                    let _ = tx.try_send(MergeStatus::ByteProgress { part: part_number, bytes_written, part_size: package.size });
+                }
+            };
+
+            let (copy_result, _) = tokio::join!(copy_pkg_file(&package_path, &temp_path, package.offset, Some(progress_tx)), forward_progress);
+
+            match copy_result {
+                Ok(read_length) => {
+                    // Nobody might be listening anymore, but there's no reason to leave a half-merged file behind.
+                    let _ = tx.send(MergeStatus::PartProgress(part_number)).await;
+                    info!("merged {} bytes from {} to {}", read_length, file_name, merged_file_name);
+                },
+                Err(err) => {
+                    error!("could not merge files: {}", err.to_string());
+                    return Err(MergeError::FileMergeFailure)
+                },
+            };
+        }
+
+        let mut verified_versions = Vec::new();
+
+        for (temp_path, merged_path, version) in merged_files {
+            if let Some(expected) = self.package_digests.get(&version) {
+                let _ = tx.send(MergeStatus::Verifying).await;
+
+                if self.verify_merged_file(&temp_path, &version, expected).await? {
+                    verified_versions.push(version.clone());
+                }
+            }
+
+            if delete_parts && !verified_versions.contains(&version) {
+                warn!("Keeping the parts of {} {version}, the merged file couldn't be checked against a digest", self.title_id);
+            }
+
+            if let Err(e) = move_file(&temp_path, &merged_path).await {
+                error!("could not move merged file {:?} to {:?}: {e}", temp_path, merged_path);
+                return Err(MergeError::FileMergeFailure);
+            }
+        }
+
+        // Nothing gets deleted when none of the merged files could be checked, so there's no clean-up phase to report.
+        if delete_parts && !verified_versions.is_empty() {
+            let _ = tx.send(MergeStatus::CleaningUp).await;
+
+            let mut deletions = tokio::task::JoinSet::new();
+
+            for package in self.packages.iter() {
+                if !verified_versions.contains(&package.version) {
+                    continue;
+                }
+
+                if let Some(file_name) = package.file_name() {
+                    let part_path = package_download_path.join(&file_name);
+                    deletions.spawn(async move {
+                        let result = tokio::fs::remove_file(&part_path).await;
+                        (part_path, result)
+                    });
+                }
+            }
+
+            // The merged files are already in place, a part that can't be removed only wastes some space.
+            while let Some(deletion) = deletions.join_next().await {
+                match deletion {
+                    Ok((part_path, Ok(()))) => info!("Deleted merged part {:?}", part_path),
+                    Ok((part_path, Err(e))) => warn!("could not delete merged part {:?}: {e}", part_path),
+                    Err(e) => warn!("could not delete a merged part: {e}")
+                }
+            }
+        }
+
+        let _ = tx.send(MergeStatus::MergeSuccess).await;
+        Ok(())
+    }
+}
+
+// Template for the name of merged PS4 pkgs. The available placeholders are {serial}, {version}, {content_id},
+// and {original}, which is Sony's file name for the parts without the part suffix and extension.
+pub const DEFAULT_MERGED_NAME_TEMPLATE: &str = "{original}.pkg";
+
+fn render_merged_file_name(template: &str, title_id: &str, version: &str, original_name: &str, multiple_versions: bool) -> String {
+    // Sony's file names start with the content id, eg. UP0000-CUSA00000_00-XXXXXXXXXXXXXXXX-A0101-V0100.
+    let content_id = original_name
+        .get(..36)
+        .filter(| id | id.matches('-').count() == 2)
+        .unwrap_or(title_id)
+    ;
+
+    let mut name = template
+        .replace("{serial}", title_id)
+        .replace("{version}", version)
+        .replace("{content_id}", content_id)
+        .replace("{original}", original_name)
+        // The name can't be allowed to point outside of the title's folder.
+        .replace(| c: char | c == '/' || c == '\\', "_")
+    ;
+
+    // Without something version-specific in the name, every version of the title would get merged into the same file.
+    if multiple_versions && !template.contains("{version}") && !template.contains("{original}") {
+        name = match name.strip_suffix(".pkg") {
+            Some(stem) => format!("{stem}_{version}.pkg"),
+            None => format!("{name}_{version}")
+        };
+    }
+
+    name
+}
+
+// Versions the user wants to skip, or to exclusively download, as `[SERIAL:]VERSION` patterns.
+// The version part can use `*` as a wildcard, eg. `01.0*` or `BLUS30443:02.*`.
+#[derive(Clone, Default)]
+pub struct VersionFilter {
+    pub allowlist: Vec<String>,
+    pub blocklist: Vec<String>
+}
+
+impl VersionFilter {
+    pub fn allows(&self, title_id: &str, pkg: &PackageInfo) -> bool {
+        let applies_to_title = | pattern: &&String | {
+            match pattern.split_once(':') {
+                Some((serial, _)) => parse_title_id(&serial.to_string()) == parse_title_id(&title_id.to_string()),
+                None => true
+            }
+        };
+
+        let matches_version = | pattern: &String | {
+            let version_pattern = pattern.split_once(':').map(| (_, v) | v).unwrap_or(pattern);
+            wildcard_matches(version_pattern.trim(), &pkg.version)
+        };
+
+        if self.blocklist.iter().filter(applies_to_title).any(matches_version) {
+            return false;
+        }
+
+        // An allowlist only restricts the titles it has entries for.
+        let mut allowlist = self.allowlist.iter().filter(applies_to_title).peekable();
+        allowlist.peek().is_none() || allowlist.any(matches_version)
+    }
+}
+
+fn wildcard_matches(pattern: &str, value: &str) -> bool {
+    match pattern.split_once('*') {
+        Some((prefix, rest)) => {
+            if !value.starts_with(prefix) {
+                return false;
+            }
+
+            let remaining = &value[prefix.len()..];
+            (0..=remaining.len())
+                .filter(| i | remaining.is_char_boundary(*i))
+                .any(| i | wildcard_matches(rest, &remaining[i..]))
+        }
+        None => pattern == value
+    }
+}
+
+// Packages that are byte-identical to a package of another serial, going by their SHA-1 hashes.
+// Regional variants of a title often share the same update files. Maps each hash to the (serial, package id) pairs that have it.
+pub fn find_duplicate_packages(updates: &[UpdateInfo]) -> HashMap<String, Vec<(String, String)>> {
+    let mut packages: HashMap<String, Vec<(String, String)>> = HashMap::new();
+
+    for update in updates.iter() {
+        for pkg in update.packages.iter().filter(| pkg | !pkg.sha1sum.is_empty()) {
+            packages.entry(pkg.sha1sum.to_lowercase())
+                .or_default()
+                .push((update.title_id.clone(), pkg.id()))
+            ;
+        }
+    }
+
+    packages.retain(| _, entries | entries.iter().any(| (serial, _) | *serial != entries[0].0));
+    packages
+}
+
+pub fn parse_title_id(title_id: &String) -> String {
+    return title_id
+        .trim()
+        .replace("-", "") // strip the dash that some sites put in a title id, eg. BCES-xxxxx
+        .to_uppercase();
+}
+
+// Serials from a list with one per line, like the ones people keep of their collection.
+// Empty lines and ones starting with # are skipped, and each serial is only returned once.
+pub fn parse_serial_list(contents: &str) -> Vec<String> {
+    let mut serials: Vec<String> = Vec::new();
+
+    for line in contents.lines().map(| l | l.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let serial = parse_title_id(&line.to_string());
+        if !serials.contains(&serial) {
+            serials.push(serial);
+        }
+    }
+
+    serials
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PackageInfo {
+    pub url: String,
+    pub size: u64,
+    pub version: String,
+    // The package's hash in hex, SHA-1 unless hash_algorithm says otherwise.
+    pub sha1sum: String,
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    pub hash_whole_file: bool,
+    pub manifest_url: String,
+    // Patch notes for the update, if Sony has any. Empty otherwise.
+    pub changeinfo_url: String,
+    // Minimum system software to install the update, as Sony writes it (eg. 04.8500). Only PS3 updates have it.
+    pub required_firmware: Option<String>,
+    pub offset: u64,
+    pub part_number: Option<usize>,
+    // Whether the declared size might leave out the hash suffix, which only happens with PS3 updates. See is_short_transfer.
+    #[serde(default)]
+    pub size_may_skip_suffix: bool,
+}
+
+impl PackageInfo {
+    fn empty() -> PackageInfo {
+        PackageInfo {
+            url: String::new(),
+            size: 0,
+            version: String::new(),
+            sha1sum: String::new(),
+            hash_algorithm: HashAlgorithm::Sha1,
+            hash_whole_file: false,
+            manifest_url: String::new(),
+            changeinfo_url: String::new(),
+            required_firmware: None,
+            offset: 0,
+            part_number: None,
+            size_may_skip_suffix: false,
+        }
+    }
+
+    // The required firmware in the format used by the console's menus, eg. 04.8500 -> 4.85.
+    pub fn firmware_version(&self) -> Option<String> {
+        let (major, minor) = self.required_firmware.as_ref()?.split_once('.')?;
+        let major = major.parse::<u32>().ok()?;
+        let minor = minor.get(..2).unwrap_or(minor);
+
+        Some(format!("{major}.{minor}"))
+    }
+
+    pub fn id(&self) -> String {
+        match self.part_number {
+            Some(part_idx) => format!("{0} - Part {1}", self.version, part_idx),
+            None => self.version.to_owned()
+        }
+    }
+
+    // Parses the hex hash from the update's info into its raw bytes. None if it isn't valid for the package's algorithm.
+    pub fn hash_bytes(&self) -> Option<Vec<u8>> {
+        hex::decode(&self.sha1sum).ok().filter(| bytes | bytes.len() == self.hash_algorithm.digest_len())
+    }
+
+    // Checks a file against the package's hash. Vita and PSP updates don't list one, so for those
+    // (or any package with a hash that doesn't parse) a file with the expected size is taken as complete.
+    async fn matches_file(&self, file: &mut tokio::fs::File, backend: Sha1Backend) -> Result<bool, DownloadError> {
+        match self.hash_bytes() {
+            Some(hash) => crate::utils::hash_file(file, &hash, self.hash_whole_file, self.hash_algorithm, backend).await,
+            None => {
+                let size = file.metadata().await.map_err(DownloadError::Tokio)?.len();
+                Ok(self.has_expected_size(size))
+            }
+        }
+    }
+
+    async fn matches_file_at(&self, path: &Path, backend: Sha1Backend) -> Result<bool, DownloadError> {
+        let mut file = tokio::fs::File::open(path).await.map_err(| error | DownloadError::IoWithPath { error, path: path.to_path_buf() })?;
+        self.matches_file(&mut file, backend).await
+    }
+
+    fn has_expected_size(&self, size: u64) -> bool {
+        size > 0 && (self.size == 0 || size == self.size)
+    }
+
+    // Where the package ends up when it's downloaded to download_path.
+    pub fn file_path(&self, download_path: &Path, serial: &str, title: &str, strict_names: bool) -> PathBuf {
+        let file_name = self.file_name().unwrap_or_else(|| String::from("update.pkg"));
+        crate::utils::resolve_pkg_path(&download_path.to_path_buf(), serial, title, &file_name, strict_names)
+    }
+
+    // Checks the file at the given path against the package, without downloading anything.
+    // Everything that verifies files on disk goes through here, so they all agree on what's complete.
+    pub async fn check_file(&self, path: &Path, backend: Sha1Backend) -> Result<PkgFileStatus, DownloadError> {
+        let size = match tokio::fs::metadata(path).await {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                // Unfinished downloads are still in their temp file.
+                let temp_path = crate::utils::temp_pkg_path(path);
+
+                return match tokio::fs::metadata(&temp_path).await {
+                    Ok(_) => Ok(PkgFileStatus::Partial { size: received_length(&temp_path).await? }),
+                    Err(_) => Ok(PkgFileStatus::Missing)
+                };
+            }
+            Err(error) => return Err(DownloadError::IoWithPath { error, path: path.to_path_buf() })
+        };
+
+        if self.is_short_transfer(size) {
+            return Ok(PkgFileStatus::Partial { size });
+        }
+
+        if self.matches_file_at(path, backend).await? {
+            return Ok(PkgFileStatus::Verified);
+        }
+
+        // A download that got killed midway has the full size, with zeroes where the rest of the data should be.
+        let received = received_length(path).await?;
+
+        if self.is_short_transfer(received) {
+            Ok(PkgFileStatus::Partial { size: received })
+        }
+        else {
+            Ok(PkgFileStatus::Mismatched)
+        }
+    }
+
+    pub async fn start_download(&self, tx: Sender<DownloadStatus>, download_path: PathBuf, serial: String, title: String, config: DownloadConfig) -> Result<(), DownloadError> {
+        info!("Starting download for for {serial} {}", self.version);
+
+        self.check_disk_space(&download_path, &serial, &title, &config).await?;
+
+        let file_name = self.file_name().unwrap_or_else(|| String::from("update.pkg"));
+        let pkg_path = crate::utils::create_pkg_path(download_path, &serial, &title, &file_name, config.strict_file_names).await;
+
+        self.ensure_complete(tx, &pkg_path, config).await
+    }
+
+    // Makes sure the file at the given path is this package, fully downloaded and with the right hash.
+    // A file that's already complete is left alone, otherwise it gets downloaded again and verified.
+    // Safe to call repeatedly, it only talks to the CDN when the file actually needs to be (re)downloaded.
+    // The data is written to a temp file next to the path, which is only moved there once it's verified,
+    // so a file at the path is never a partial download.
+    pub async fn ensure_complete(&self, tx: Sender<DownloadStatus>, path: &Path, config: DownloadConfig) -> Result<(), DownloadError> {
+        let temp_path = crate::utils::temp_pkg_path(path);
+
+        if self.hash_bytes().is_none() {
+            warn!("No usable {} hash for {} ({:?}), it will only be checked by its size", self.hash_algorithm, self.id(), self.sha1sum);
+        }
+
+        let mut already_hashed = false;
+
+        if let Ok(metadata) = tokio::fs::metadata(path).await {
+            let existing_size = metadata.len();
+
+            // The size alone doesn't tell a killed download apart, those are preallocated.
+            if config.skip_verify_existing && self.size > 0 && existing_size == self.size && !self.is_short_transfer(received_length(path).await?) {
+                info!("File for {} already exists with the expected size, skipping verification", self.id());
+                let _ = tx.send(DownloadStatus::DownloadSuccess).await;
+
+                return Ok(());
+            }
+
+            match config.existing_status {
+                Some(PkgFileStatus::Verified) => {
+                    info!("File for {} was already checked and is complete, wrapping up...", self.id());
+                    let _ = tx.send(DownloadStatus::DownloadSuccess).await;
+
+                    return Ok(());
+                }
+                Some(PkgFileStatus::Partial { .. }) | Some(PkgFileStatus::Mismatched) => already_hashed = true,
+                Some(PkgFileStatus::Missing) | None => {}
+            }
+
+            // A short file can't be complete, there's no point in hashing it.
+            if !already_hashed && !self.is_short_transfer(existing_size) {
+                send_download_status(&tx, DownloadStatus::Verifying).await?;
+
+                if self.matches_file_at(path, config.sha1_backend).await? {
+                    info!("File for {} already existed and was complete, wrapping up...", self.id());
+                    let _ = tx.send(DownloadStatus::DownloadSuccess).await;
+
+                    return Ok(());
+                }
+
+                already_hashed = true;
+            }
+
+            // Older versions downloaded straight into the final path, so what's there is treated as an interrupted download.
+            info!("File for {} at {:?} isn't complete, moving it to {:?} to continue from there", self.id(), path, temp_path);
+            move_file(path, &temp_path).await.map_err(| error | DownloadError::IoWithPath { error, path: path.to_path_buf() })?;
+        }
+
+        let mut pkg_file = crate::utils::open_pkg_file(&temp_path).await?;
+        let existing_size = pkg_file.metadata().await.map(| m | m.len()).unwrap_or(0);
+
+        // Failed transfers are cut down to what was received, so a short file can be picked up where it stopped.
+        let mut resume_from = if existing_size > 0 && self.is_short_transfer(existing_size) { existing_size } else { 0 };
+
+        if resume_from == 0 && existing_size > 0 {
+            // Getting interrupted between verifying a download and moving it into place leaves a complete temp file behind.
+            if !already_hashed {
+                send_download_status(&tx, DownloadStatus::Verifying).await?;
+
+                if self.matches_file(&mut pkg_file, config.sha1_backend).await? {
+                    info!("Found a complete download of {} at {:?}, moving it into place", self.id(), temp_path);
+                    finish_pkg_file(pkg_file, &temp_path, path).await?;
+                    let _ = tx.send(DownloadStatus::DownloadSuccess).await;
+
+                    return Ok(());
+                }
+            }
+
+            // A download that got killed never got to cut its preallocated file down, so the zeroed tail is what's missing.
+            let received = crate::utils::data_length(&mut pkg_file).await.map_err(DownloadError::Tokio)?;
+
+            if received > 0 && self.is_short_transfer(received) {
+                resume_from = received;
+            }
+        }
+
+        if resume_from > 0 {
+            info!("Found a partial download of {} at {:?}, resuming it from byte {resume_from}", self.id(), temp_path);
+        }
+
+        // Retries go through the same client, so they can reuse its connection to the CDN.
+        let config = config.with_shared_client().map_err(DownloadError::Reqwest)?;
+
+        let mut attempt = 0;
+
+        loop {
+            let result = self.download_into(&tx, &mut pkg_file, &config, resume_from).await;
+
+            let retryable = match &result {
+                Ok(received) => self.is_short_transfer(*received),
+                Err(DownloadError::Reqwest(_)) | Err(DownloadError::TimedOut) => true,
+                Err(DownloadError::Tokio(e)) => e.kind() == tokio::io::ErrorKind::TimedOut,
+                Err(_) => false
+            };
+
+            if retryable && attempt < config.max_retries {
+                attempt += 1;
+                let delay = retry_delay(attempt);
+
+                match &result {
+                    Ok(received) => warn!("Transfer of {} stopped after {received} of {} bytes, retrying in {}s ({attempt}/{})", self.id(), self.size, delay.as_secs(), config.max_retries),
+                    Err(e) => warn!("Transfer of {} failed ({e}), retrying in {}s ({attempt}/{})", self.id(), delay.as_secs(), config.max_retries)
+                }
+
+                send_download_status(&tx, DownloadStatus::Retrying { attempt, max: config.max_retries, delay }).await?;
+                tokio::time::sleep(delay).await;
+
+                // Failed transfers are cut down to what was received, so the file's size is where to pick up from.
+                resume_from = pkg_file.metadata().await.map(| m | m.len()).unwrap_or(0);
+                continue;
+            }
+
+            let received_data = result?;
+
+            // Without a hash, the size is all there is to check.
+            let (matched, expected, actual) = match self.hash_bytes() {
+                Some(expected_hash) => {
+                    info!("No more chunks available, hashing received file for {}", self.id());
+                    send_download_status(&tx, DownloadStatus::Verifying).await?;
+
+                    let actual_hash = crate::utils::compute_file_hash(&mut pkg_file, self.hash_whole_file, self.hash_algorithm, config.sha1_backend).await?;
+                    (actual_hash == expected_hash, hex::encode(expected_hash), hex::encode(actual_hash))
+                }
+                None => {
+                    warn!("Skipping verification of {}, there's no hash to check it against", self.id());
+                    (self.has_expected_size(received_data), format!("{} bytes", self.size), format!("{received_data} bytes"))
+                }
+            };
+
+            if matched {
+                info!("Download of {} checked out, wrapping up...", self.id());
+                finish_pkg_file(pkg_file, &temp_path, path).await?;
+                let _ = tx.send(DownloadStatus::DownloadSuccess).await;
+
+                return Ok(());
+            }
+
+            // There's no telling which part of the file is wrong, so it's downloaded again from the start.
+            if attempt < config.max_retries {
+                attempt += 1;
+                let delay = retry_delay(attempt);
+
+                warn!("Hash mismatch for {} (expected {expected}, got {actual}), downloading it again in {}s ({attempt}/{})", self.id(), delay.as_secs(), config.max_retries);
+
+                send_download_status(&tx, DownloadStatus::Retrying { attempt, max: config.max_retries, delay }).await?;
+                tokio::time::sleep(delay).await;
+
+                // Lets the receiver know the progress so far doesn't count anymore.
+                send_download_status(&tx, DownloadStatus::Resuming(0)).await?;
+
+                resume_from = 0;
+                continue;
+            }
+
+            error!("Hash mismatch for {}! Expected {expected}, got {actual}", self.id());
+
+            if config.quarantine_failed {
+                // The file has to be closed before it can be moved on Windows.
+                drop(pkg_file);
+
+                match quarantine_file(&temp_path, path).await {
+                    Ok(quarantine_path) => info!("Moved failed download of {} to {:?}", self.id(), quarantine_path),
+                    Err(e) => error!("Failed to move failed download of {} to quarantine: {e}", self.id())
+                }
+            }
+
+            let _ = tx.send(DownloadStatus::DownloadFailure).await;
+
+            return Err(DownloadError::HashMismatch {
+                short_on_data: self.is_short_transfer(received_data),
+                expected,
+                actual
+            });
+        }
+    }
+
+    // Writes the pkg to the file, starting at resume_from if the server supports ranges and from the start otherwise.
+    // Returns how much of the pkg is in the file afterwards.
+    async fn download_into(&self, tx: &Sender<DownloadStatus>, pkg_file: &mut tokio::fs::File, config: &DownloadConfig, resume_from: u64) -> Result<u64, DownloadError> {
+        info!("Sending pkg file request to url: {}", &self.url);
+
+        let client = match &config.client {
+            Some(client) => client.clone(),
+            None => config.build_client().map_err(DownloadError::Reqwest)?
+        };
+        let started_at = std::time::Instant::now();
+
+        let mut resume_from = resume_from;
+        let mut response = self.request_pkg(&client, resume_from).await?;
+
+        info!("Response received for {}", self.id());
+        debug!("Response for {} took {} ms", self.id(), started_at.elapsed().as_millis());
+
+        if resume_from > 0 {
+            if response.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+                info!("Resuming download of {} from byte {resume_from}", self.id());
+            }
+            else {
+                info!("Server didn't resume {} ({}), downloading it from the start", self.id(), response.status());
+                resume_from = 0;
+
+                // A server without range support just sends the whole file, but one that rejected the range has to be asked again.
+                if !response.status().is_success() {
+                    response = self.request_pkg(&client, 0).await?;
+                }
+            }
+
+            // Also sent when starting over, so progress from a previous attempt isn't counted twice.
+            send_download_status(tx, DownloadStatus::Resuming(resume_from)).await?;
+        }
+
+        if resume_from == 0 {
+            if let Err(e) = pkg_file.set_len(0).await {
+                error!("Failed to set file lenght to 0: {e}");
+                return Err(DownloadError::Tokio(e));
+            }
+        }
+
+        // Preallocating the whole file avoids fragmenting big pkgs, and on some filesystems
+        // running out of space fails here instead of halfway through the download.
+        if self.size > 0 {
+            if let Err(e) = pkg_file.set_len(self.size).await {
+                error!("Failed to preallocate {} bytes for pkg file: {e}", self.size);
+                return Err(DownloadError::Tokio(e));
+            }
+        }
+
+        // Hashing moved the seek pointer, data has to be written from where the transfer starts.
+        pkg_file.seek(SeekFrom::Start(resume_from)).await.map_err(DownloadError::Tokio)?;
+
+        let mut received_data = resume_from;
+        let mut last_speed_report = std::time::Instant::now();
+        let mut received_since_report = 0;
+
+        let transfer_result: Result<(), DownloadError> = async {
+            loop {
+                let next_chunk = match config.transfer_timeout {
+                    Some(timeout) => {
+                        tokio::time::timeout(timeout, response.chunk())
+                            .await
+                            .map_err(| _ | DownloadError::TimedOut)?
+                    }
+                    None => response.chunk().await
+                };
+
+                let download_chunk = match next_chunk.map_err(DownloadError::from_reqwest)? {
+                    Some(chunk) => chunk,
+                    None => break
+                };
+
+                let download_chunk = download_chunk.as_ref();
+                let download_chunk_len = download_chunk.len() as u64;
+
+                received_data += download_chunk_len;
+                debug!("Received a {} bytes chunk for {}", download_chunk_len, self.id());
+
+                if let Some(limiter) = config.rate_limit.as_ref() {
+                    limiter.acquire(download_chunk_len).await;
+                }
+
+                send_download_status(tx, DownloadStatus::Progress(download_chunk_len)).await?;
+
+                received_since_report += download_chunk_len;
+                let since_report = last_speed_report.elapsed();
+
+                if since_report >= SPEED_REPORT_INTERVAL {
+                    let bytes_per_sec = (received_since_report as f64 / since_report.as_secs_f64()) as u64;
+                    let eta_secs = if bytes_per_sec > 0 { Some(self.size.saturating_sub(received_data) / bytes_per_sec) } else { None };
+
+                    send_download_status(tx, DownloadStatus::Speed { bytes_per_sec, eta_secs }).await?;
+
+                    last_speed_report = std::time::Instant::now();
+                    received_since_report = 0;
+                }
+
+                if let Err(e) = pkg_file.write_all(download_chunk).await {
+                    error!("Failed to write chunk data: {e}");
+                    return Err(DownloadError::Tokio(e));
+                }
+            }
+
+            Ok(())
+        }.await;
+
+        // If the transfer stopped early, the preallocated file would still have the full size.
+        // Cut it down to what was actually received, so it's not mistaken for a complete file.
+        if received_data < self.size {
+            if let Err(e) = pkg_file.set_len(received_data).await {
+                error!("Failed to truncate pkg file to the received size: {e}");
+                return Err(DownloadError::Tokio(e));
+            }
+        }
+
+        transfer_result?;
+
+        if let Err(e) = pkg_file.sync_all().await {
+            error!("Failed to flush all data to file: {e}");
+            return Err(DownloadError::Tokio(e));
+        }
+
+        if self.is_short_transfer(received_data) {
+            warn!("Received less data than expected for pkg file! Expected {} bytes, received {} bytes.", self.size, received_data)
+        }
+
+        Ok(received_data)
+    }
+
+    async fn request_pkg(&self, client: &reqwest::Client, resume_from: u64) -> Result<reqwest::Response, DownloadError> {
+        let mut request = client.get(&self.url);
+
+        if resume_from > 0 {
+            request = request
+                .header(reqwest::header::RANGE, format!("bytes={resume_from}-"))
+                // The range would apply to the compressed data, which can't be decoded starting halfway through.
+                .header(reqwest::header::ACCEPT_ENCODING, "identity")
+            ;
+        }
+
+        request.send().await.map_err(DownloadError::from_reqwest)
+    }
+
+    // The size that PS3 update XMLs declare isn't consistent about the 0x20 bytes hash suffix at the end of the pkg.
+    // Most include it, but some (seemingly the ones also offered as background downloads) only count the data before it.
+    // Being short by up to the suffix size isn't a reliable sign of truncation, so it only counts when more than that is missing.
+    // The other platforms declare exact sizes, PS4 ones even come from the manifest pieces.
+    fn is_short_transfer(&self, received: u64) -> bool {
+        let tolerance = if self.size_may_skip_suffix { 0x20 } else { 0 };
+        received + tolerance < self.size
+    }
+
+    async fn check_disk_space(&self, download_path: &PathBuf, serial: &str, title: &str, config: &DownloadConfig) -> Result<(), DownloadError> {
+        let reserve = config.min_free_space;
+        let mut target_path = create_new_pkg_path(download_path, serial, title, config.strict_file_names);
+
+        // Whatever is already on disk either gets kept or truncated and written over,
+        // so only the remaining part of the file needs to fit.
+        let existing_size = match self.file_name() {
+            Some(name) => {
+                target_path.push(name);
+
+                match tokio::fs::metadata(&target_path).await {
+                    Ok(metadata) => metadata.len(),
+                    Err(_) => tokio::fs::metadata(crate::utils::temp_pkg_path(&target_path)).await.map(| m | m.len()).unwrap_or(0)
+                }
+            }
+            None => 0
+        };
+
+        let required = with_space_buffer(self.size.saturating_sub(existing_size)) + reserve;
+
+        match crate::utils::available_space(&target_path) {
+            Ok(available) => {
+                if available < required {
+                    error!("Not enough disk space for {serial} {}: {required} bytes required, {available} bytes available", self.version);
+                    return Err(DownloadError::InsufficientDiskSpace { required, available });
+                }
+            }
+            Err(e) => warn!("Failed to check available disk space for {:?}: {e}", target_path)
+        }
+
+        Ok(())
+    }
+
+    // Saves the update's patch notes to the title's folder, next to the pkgs. Updates without notes are skipped.
+    // The notes aren't essential, so failing to get them is only logged. Returns the path of the saved notes.
+    pub async fn save_changeinfo<C: PsnClient>(&self, client: &C, download_path: PathBuf, serial: &str, title: &str, strict_names: bool) -> Option<PathBuf> {
+        if self.changeinfo_url.is_empty() {
+            return None;
+        }
+
+        let notes = match client.get_text(&self.changeinfo_url).await {
+            Ok(notes) => notes,
+            Err(e) => {
+                warn!("Failed to fetch patch notes for {serial} {}: {:?}", self.version, e);
+                return None;
+            }
+        };
+
+        // Every part of a PS4 update shares the same notes, so they're named after the version.
+        let extension = Path::new(&self.changeinfo_url)
+            .extension()
+            .and_then(| e | e.to_str())
+            .unwrap_or("xml")
+        ;
+
+        let notes_name = format!("changeinfo-{}.{extension}", self.version);
+        let mut notes_path = create_new_pkg_path(&download_path, serial, title, strict_names);
+        notes_path.push(&notes_name);
+
+        let result = async {
+            let mut notes_file = crate::utils::create_pkg_file(download_path, serial, title, &notes_name, strict_names).await?;
+
+            notes_file.set_len(0).await.map_err(DownloadError::Tokio)?;
+            notes_file.write_all(notes.as_bytes()).await.map_err(DownloadError::Tokio)?;
+            notes_file.sync_all().await.map_err(DownloadError::Tokio)
+        };
+
+        match result.await {
+            Ok(()) => {
+                info!("Saved patch notes for {serial} {} to {:?}", self.version, notes_path);
+                Some(notes_path)
+            }
+            Err(e) => {
+                warn!("Failed to save patch notes for {serial} {}: {e}", self.version);
+                None
+            }
+        }
+    }
+
+    pub fn file_name(&self) -> Option<String> {
+        let pkg_url = match Url::from_str(&self.url) {
+            Ok(url) => url,
+            Err(_) => return None
+        };
+
+        let file_name = pkg_url
+            .path_segments()
+            .and_then(|s| s.last())
+            .and_then(|n| if n.is_empty() { None } else { Some(n.to_string()) });
+
+        file_name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    const AC3_RESPONSE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<titlepatch titleid="NPUB30826">
+<tag name="NPUB30826_T3" popup="true" signoff="true">
+<package version="01.01" size="20480" sha1sum="da39a3ee5e6b4b0d3255bfef95601890afd80709" url="http://b0.ww.np.dl.playstation.net/tppkg/np/NPUB30826/NPUB30826_T3/UP0001-NPUB30826_00-ASSASSINSCREED3X-A0101-V0100-PE.pkg" ps3_system_ver="04.3000">
+<paramsfo><TITLE>Assassin's Creed® III</TITLE></paramsfo>
+</package>
+</tag>
+</titlepatch>"#;
+
+    fn mock_client_for(title_id: &str, response: &str) -> super::client::MockPsnClient {
+        let url = super::utils::get_update_info_url(title_id, super::utils::PlaformVariant::PS3).unwrap();
+        super::client::MockPsnClient::new().with_response(url, response)
+    }
+
+    #[tokio::test]
+    async fn parse_mock_ac3() {
+        let client = mock_client_for("NPUB30826", AC3_RESPONSE);
+
+        match super::UpdateInfo::get_info_with(&client, "NPUB30826".to_string()).await {
+            Ok(info) => {
+                assert_eq!(info.title_id, "NPUB30826");
+                assert_eq!(info.title(), "Assassin's Creed® III");
+                assert_eq!(info.packages.len(), 1);
+                assert_eq!(info.packages[0].version, "01.01");
+                assert_eq!(info.packages[0].size, 20480);
+            }
+            Err(e) => panic!("Failed to get info for NPUB30826: {:?}", e)
+        }
+    }
+
+    #[tokio::test]
+    async fn parse_mock_bom_prefixed() {
+        let response = format!("\u{feff}{AC3_RESPONSE}");
+        let client = mock_client_for("NPUB30826", &response);
+
+        match super::UpdateInfo::get_info_with(&client, "NPUB30826".to_string()).await {
+            Ok(info) => {
+                assert_eq!(info.title_id, "NPUB30826");
+                assert_eq!(info.packages.len(), 1);
+                assert_eq!(info.packages[0].version, "01.01");
+            }
+            Err(e) => panic!("Failed to get info for a BOM-prefixed response: {:?}", e)
+        }
+    }
+
+    #[tokio::test]
+    async fn parse_mock_not_found() {
+        let client = mock_client_for("NPUB30826", "Not found");
+
+        match super::UpdateInfo::get_info_with(&client, "NPUB30826".to_string()).await {
+            Err(super::UpdateError::InvalidSerial) => {}
+            Err(e) => panic!("Unexpected error for NPUB30826: {:?}", e),
+            Ok(_) => panic!("Got results for a missing serial")
+        }
+    }
+
+    const VITA_RESPONSE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<titlepatch titleid="PCSB00245">
+<tag name="PCSB00245_00" mandatory="false">
+<package version="01.01" type="cumulative" size="73728" digest="0000000000000000000000000000000000000000000000000000000000000000" url="http://gs.ww.np.dl.playstation.net/ppkg/np/PCSB00245/PCSB00245_T2/0000000000000000/EP9000-PCSB00245_00-PERSONA4GOLDEN00-A0101-V0100.pkg" psp2_system_ver="01650000">
+<paramsfo><title>Persona 4 Golden</title></paramsfo>
+</package>
+</tag>
+</titlepatch>"#;
+
+    #[tokio::test]
+    async fn parse_mock_vita() {
+        assert_eq!(super::get_platform_variant("PCSB00245"), Some(super::PlaformVariant::PSV));
+
+        let url = super::utils::get_update_info_url("PCSB00245", super::PlaformVariant::PSV).unwrap();
+        assert_eq!(url, "https://gs-sec.ww.np.dl.playstation.net/pl/np/PCSB00245/a76e4dfed4ea0df7745507c5d7f071b45b9087a8b4fb5c27ecce159b6a600841/PCSB00245-ver.xml");
+
+        let client = super::client::MockPsnClient::new().with_response(url, VITA_RESPONSE);
+
+        match super::UpdateInfo::get_info_with(&client, "PCSB00245".to_string()).await {
+            Ok(info) => {
+                assert_eq!(info.platform_variant, super::PlaformVariant::PSV);
+                assert_eq!(info.title(), "Persona 4 Golden");
+                assert_eq!(info.packages.len(), 1);
+                assert_eq!(info.packages[0].version, "01.01");
+                assert_eq!(info.packages[0].size, 73728);
+            }
+            Err(e) => panic!("Failed to get info for PCSB00245: {:?}", e)
+        }
+    }
+
+    #[tokio::test]
+    async fn download_vita_pkg_without_hash() {
+        static CONTENTS: [u8; 73728] = [0x5A; 73728];
+
+        let url = super::utils::get_update_info_url("PCSB00245", super::PlaformVariant::PSV).unwrap();
+        let client = super::client::MockPsnClient::new().with_response(url, VITA_RESPONSE);
+        let info = super::UpdateInfo::get_info_with(&client, "PCSB00245".to_string()).await.unwrap();
+
+        // Vita updates only list a digest, which isn't the file's hash.
+        let mut pkg = info.packages[0].clone();
+        assert!(pkg.hash_bytes().is_none());
+
+        let address = serve_once("", &CONTENTS).await;
+        pkg.url = format!("http://{address}/EP9000-PCSB00245_00-PERSONA4GOLDEN00-A0101-V0100.pkg");
+
+        let pkg_path = std::env::temp_dir().join("rusty-psn-vita-download-test").join(pkg.file_name().unwrap());
+        let _ = std::fs::remove_dir_all(pkg_path.parent().unwrap());
+        std::fs::create_dir_all(pkg_path.parent().unwrap()).unwrap();
+
+        let config = super::DownloadConfig { max_retries: 0, ..Default::default() };
+        let (tx, _rx) = tokio::sync::mpsc::channel(32);
+
+        if let Err(e) = pkg.ensure_complete(tx, &pkg_path, config.clone()).await {
+            panic!("A Vita pkg failed to download: {:?}", e);
+        }
+
+        assert_eq!(std::fs::read(&pkg_path).unwrap().len(), CONTENTS.len());
+
+        // Already complete, so it's kept without talking to the server again.
+        let (tx, _rx) = tokio::sync::mpsc::channel(32);
+        assert!(pkg.ensure_complete(tx, &pkg_path, config).await.is_ok());
+
+        let _ = std::fs::remove_dir_all(pkg_path.parent().unwrap());
+    }
+
+    #[test]
+    fn psp_serials() {
+        assert_eq!(super::get_platform_variant("NPUG80318"), Some(super::PlaformVariant::PSP));
+        assert_eq!(super::get_platform_variant("ULUS10041"), Some(super::PlaformVariant::PSP));
+        assert_eq!(super::get_platform_variant("NPUB30826"), Some(super::PlaformVariant::PS3));
+
+        let url = super::utils::get_update_info_url("NPUG80318", super::PlaformVariant::PSP).unwrap();
+        assert_eq!(url, "http://fu01.psn.np.dl.playstation.net/pl/np/NPUG80318/NPUG80318-ver.xml");
+
+        assert_eq!(super::PlaformVariant::PSP.to_string(), "PSP");
+        assert_eq!(super::PlaformVariant::PSV.to_string(), "PSVita");
+    }
+
+    const PSP_RESPONSE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<titlepatch titleid="NPUG80318">
+<tag name="NPUG80318_T1">
+<package version="01.01" size="64" url="http://example.com/UP9000-NPUG80318_00-PATAPON200000000-A0101-V0100.pkg"/>
+</tag>
+</titlepatch>"#;
+
+    #[tokio::test]
+    async fn psp_pkg_is_checked_by_size() {
+        let url = super::utils::get_update_info_url("NPUG80318", super::PlaformVariant::PSP).unwrap();
+        let client = super::client::MockPsnClient::new().with_response(url, PSP_RESPONSE);
+        let info = super::UpdateInfo::get_info_with(&client, "NPUG80318".to_string()).await.unwrap();
+
+        let mut pkg = info.packages[0].clone();
+        assert!(pkg.hash_bytes().is_none());
+
+        // Nothing listens on the pkg's port, so this fails if the complete file isn't kept.
+        pkg.url = format!("http://{}/UP9000-NPUG80318_00-PATAPON200000000-A0101-V0100.pkg", unreachable_address());
+
+        let pkg_path = std::env::temp_dir().join("rusty-psn-psp-check-test").join(pkg.file_name().unwrap());
+        let _ = std::fs::remove_dir_all(pkg_path.parent().unwrap());
+        std::fs::create_dir_all(pkg_path.parent().unwrap()).unwrap();
+
+        std::fs::write(&pkg_path, [0x5A; 64]).unwrap();
+        assert!(matches!(pkg.check_file(&pkg_path, super::Sha1Backend::default()).await, Ok(super::PkgFileStatus::Verified)));
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(32);
+        assert!(pkg.ensure_complete(tx, &pkg_path, super::DownloadConfig::default()).await.is_ok());
+
+        std::fs::write(&pkg_path, [0x5A; 80]).unwrap();
+        assert!(matches!(pkg.check_file(&pkg_path, super::Sha1Backend::default()).await, Ok(super::PkgFileStatus::Mismatched)));
+
+        let _ = std::fs::remove_dir_all(pkg_path.parent().unwrap());
+    }
+
+    const PS4_RESPONSE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<titlepatch titleid="CUSA00000">
+<tag name="" mandatory="true">
+<package version="01.01" size="200" manifest_url="http://example.com/CUSA00000-A0101.json"></package>
+<package version="01.02" size="100" manifest_url="http://example.com/CUSA00000-A0102.json"></package>
+</tag>
+</titlepatch>"#;
+
+    const PS4_MANIFEST: &str = r#"{
+        "originalFileSize": 100,
+        "packageDigest": "",
+        "numberOfSplitFiles": 1,
+        "pieces": [
+            { "url": "http://example.com/UP0000-CUSA00000_00-0000000000000000-A0101-V0100.pkg", "fileOffset": 0, "fileSize": 100, "hashValue": "a" }
+        ]
+    }"#;
+
+    fn mock_ps4_client() -> super::client::MockPsnClient {
+        let url = super::utils::get_update_info_url("CUSA00000", super::utils::PlaformVariant::PS4).unwrap();
+
+        super::client::MockPsnClient::new()
+            .with_response(url, PS4_RESPONSE)
+            .with_response("http://example.com/CUSA00000-A0101.json", PS4_MANIFEST)
+            .with_response("http://example.com/CUSA00000-A0102.json", PS4_MANIFEST)
+    }
+
+    #[tokio::test]
+    async fn ps4_manifest_progress() {
+        let client = mock_ps4_client();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+
+        let result = super::UpdateInfo::get_info_with_progress(&client, "CUSA00000".to_string(), Some(tx)).await;
+        assert!(result.is_ok(), "Failed to get info for CUSA00000: {:?}", result.err());
+
+        let mut progress = Vec::new();
+        while let Ok(super::SearchStatus::ManifestProgress(fetched, total)) = rx.try_recv() {
+            progress.push((fetched, total));
+        }
+
+        assert_eq!(progress, vec![(0, 2), (1, 2), (2, 2)]);
+    }
+
+    #[tokio::test]
+    async fn ps4_manifest_walk_cancelled() {
+        let client = mock_ps4_client();
+        let (tx, rx) = tokio::sync::mpsc::channel(10);
+        drop(rx);
+
+        match super::UpdateInfo::get_info_with_progress(&client, "CUSA00000".to_string(), Some(tx)).await {
+            Err(super::UpdateError::Cancelled) => {}
+            Err(e) => panic!("Unexpected error for CUSA00000: {:?}", e),
+            Ok(_) => panic!("The search wasn't cancelled")
+        }
+    }
+
+    // Serves a single response on a local port, returning the address to send the request to.
+    async fn serve_once(headers: &'static str, body: &'static [u8]) -> std::net::SocketAddr {
+        serve_once_with_status("200 OK", headers, body).await
+    }
+
+    async fn serve_once_with_status(status: &'static str, headers: &'static str, body: &'static [u8]) -> std::net::SocketAddr {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+
+            let mut request = [0; 4096];
+            let _ = socket.read(&mut request).await;
+
+            let header = format!("HTTP/1.1 {status}\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+
+            let _ = socket.write_all(header.as_bytes()).await;
+            let _ = socket.write_all(body).await;
+        });
+
+        address
+    }
+
+    // An address nothing listens on, for requests that are meant to fail. The port was free a moment ago,
+    // unlike a fixed one that something on the machine might be using.
+    fn unreachable_address() -> std::net::SocketAddr {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    // A package for contents as it would be served from address, hashed as a whole like PS4 parts are,
+    // along with an empty folder in the temp dir to download it to.
+    fn download_fixture(address: std::net::SocketAddr, file_name: &str, contents: &[u8], sha1sum: &str, folder: &str) -> (super::PackageInfo, std::path::PathBuf) {
+        let mut pkg = super::PackageInfo::empty();
+        pkg.url = format!("http://{address}/{file_name}");
+        pkg.version = String::from("01.00");
+        pkg.size = contents.len() as u64;
+        pkg.sha1sum = String::from(sha1sum);
+        pkg.hash_whole_file = true;
+
+        let folder = std::env::temp_dir().join(folder);
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+
+        (pkg, folder)
+    }
+
+    #[tokio::test]
+    async fn download_gzip_encoded_pkg() {
+        const CONTENTS: &[u8] = b"rusty-psn gzip-encoded pkg fixture";
+        const GZIP_CONTENTS: &[u8] = &[
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x2b, 0x2a, 0x2d, 0x2e, 0xa9, 0xd4,
+            0x2d, 0x28, 0xce, 0x53, 0x48, 0xaf, 0xca, 0x2c, 0xd0, 0x4d, 0xcd, 0x4b, 0xce, 0x4f, 0x49, 0x4d,
+            0x51, 0x28, 0xc8, 0x4e, 0x57, 0x48, 0xcb, 0xac, 0x28, 0x29, 0x2d, 0x4a, 0x05, 0x00, 0x6d, 0x83,
+            0x0d, 0x16, 0x22, 0x00, 0x00, 0x00
+        ];
+
+        let address = serve_once("Content-Type: application/octet-stream\r\nContent-Encoding: gzip\r\n", GZIP_CONTENTS).await;
+
+        let (pkg, download_path) = download_fixture(address, "UP0000-TEST00000_00-GZIPFIXTURE00000-A0100-V0100.pkg", CONTENTS, "b38789055a861d8ae019ecbedd83679333b6a043", "rusty-psn-gzip-test");
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(32);
+        let result = pkg.start_download(tx, download_path.clone(), String::from("TEST00000"), String::from("Gzip Test"), super::DownloadConfig::default()).await;
+
+        if let Err(e) = result {
+            panic!("Failed to download gzip-encoded pkg: {:?}", e);
+        }
+
+        let mut pkg_path = crate::utils::create_new_pkg_path(&download_path, "TEST00000", "Gzip Test", false);
+        pkg_path.push(pkg.file_name().unwrap());
+
+        assert_eq!(std::fs::read(pkg_path).unwrap(), CONTENTS);
+        let _ = std::fs::remove_dir_all(&download_path);
+    }
+
+    #[tokio::test]
+    async fn download_with_dropped_receiver() {
+        const CONTENTS: &[u8] = b"rusty-psn dropped receiver fixture";
+
+        let address = serve_once("Content-Type: application/octet-stream\r\n", CONTENTS).await;
+
+        let (pkg, download_path) = download_fixture(address, "UP0000-TEST00001_00-DROPPEDRECEIVER0-A0100-V0100.pkg", CONTENTS, "da39a3ee5e6b4b0d3255bfef95601890afd80709", "rusty-psn-dropped-receiver-test");
+
+        let (tx, rx) = tokio::sync::mpsc::channel(32);
+        drop(rx);
+
+        let result = pkg.start_download(tx, download_path.clone(), String::from("TEST00001"), String::from("Dropped Receiver Test"), super::DownloadConfig::default()).await;
+        assert!(matches!(result, Err(super::DownloadError::Cancelled)));
+
+        let _ = std::fs::remove_dir_all(&download_path);
+    }
+
+    #[tokio::test]
+    async fn download_stalled_transfer_times_out() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        // Promises more data than it sends, then keeps the connection open without sending anything else.
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+
+            let mut request = [0; 4096];
+            let _ = socket.read(&mut request).await;
+
+            let _ = socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1024\r\n\r\nrusty-psn").await;
+            tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+        });
+
+        let (pkg, download_path) = download_fixture(address, "UP0000-TEST00006_00-STALLEDTRANSFER0-A0100-V0100.pkg", &[0; 1024], "da39a3ee5e6b4b0d3255bfef95601890afd80709", "rusty-psn-stalled-transfer-test");
+
+        let config = super::DownloadConfig { transfer_timeout: Some(std::time::Duration::from_millis(200)), max_retries: 0, ..Default::default() };
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(32);
+        let result = pkg.start_download(tx, download_path.clone(), String::from("TEST00006"), String::from("Stalled Transfer Test"), config).await;
+
+        assert!(matches!(result, Err(super::DownloadError::TimedOut)));
+
+        let _ = std::fs::remove_dir_all(&download_path);
+    }
+
+    #[tokio::test]
+    async fn download_short_transfer_truncates_preallocated_file() {
+        const CONTENTS: &[u8] = b"rusty-psn short transfer fixture";
+
+        let address = serve_once("Content-Type: application/octet-stream\r\n", CONTENTS).await;
+
+        let (mut pkg, download_path) = download_fixture(address, "UP0000-TEST00002_00-SHORTTRANSFER000-A0100-V0100.pkg", CONTENTS, "da39a3ee5e6b4b0d3255bfef95601890afd80709", "rusty-psn-short-transfer-test");
+        pkg.size = CONTENTS.len() as u64 + 1024;
+
+        // Leftovers from a previous attempt get written over from the start.
+        let mut pkg_path = crate::utils::create_new_pkg_path(&download_path, "TEST00002", "Short Transfer Test", false);
+        std::fs::create_dir_all(&pkg_path).unwrap();
+        pkg_path.push(pkg.file_name().unwrap());
+        std::fs::write(&pkg_path, [0xAA; 64]).unwrap();
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(32);
+        let result = pkg.start_download(tx, download_path.clone(), String::from("TEST00002"), String::from("Short Transfer Test"), super::DownloadConfig::default()).await;
+
+        // The incomplete file stays in the temp file, to be picked up by the next attempt.
+        assert!(matches!(result, Err(super::DownloadError::HashMismatch { short_on_data: true, .. })));
+        assert!(!pkg_path.exists());
+        assert_eq!(std::fs::read(crate::utils::temp_pkg_path(&pkg_path)).unwrap(), CONTENTS);
+
+        let _ = std::fs::remove_dir_all(&download_path);
+    }
+
+    #[tokio::test]
+    async fn ps3_short_transfer_tolerance() {
+        // The last 0x20 bytes stand in for the hash suffix, which isn't part of the hashed data.
+        const CONTENTS: &[u8] = b"rusty-psn ps3 short transfer fixture0123456789abcdef0123456789abcdef";
+
+        let pkg_path = std::env::temp_dir().join("rusty-psn-ps3-short-transfer-test").join("UP0000-BLUS00000_00-PS3SHORTTRANSFR-A0100-V0100.pkg");
+        let _ = std::fs::remove_dir_all(pkg_path.parent().unwrap());
+        std::fs::create_dir_all(pkg_path.parent().unwrap()).unwrap();
+
+        // Any retry would fail, nothing listens on the server's port after the first response.
+        let config = super::DownloadConfig { max_retries: 1, ..Default::default() };
+
+        let mut pkg = super::PackageInfo::empty();
+        pkg.version = String::from("01.00");
+        pkg.size = CONTENTS.len() as u64 + 0x10;
+        pkg.sha1sum = String::from("e0dd2a23c9ca51c5e96f17ca93aeb1bc12713870");
+        pkg.size_may_skip_suffix = true;
+
+        // Closing less than the suffix size early isn't taken as a dropped transfer.
+        let address = serve_once("Content-Type: application/octet-stream\r\n", CONTENTS).await;
+        pkg.url = format!("http://{address}/UP0000-BLUS00000_00-PS3SHORTTRANSFR-A0100-V0100.pkg");
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(32);
+        if let Err(e) = pkg.ensure_complete(tx, &pkg_path, config.clone()).await {
+            panic!("A PS3 pkg within the suffix tolerance failed to download: {:?}", e);
+        }
+
+        assert_eq!(std::fs::read(&pkg_path).unwrap(), CONTENTS);
+        std::fs::remove_file(&pkg_path).unwrap();
+
+        // Closing any earlier than that is.
+        let address = serve_once("Content-Type: application/octet-stream\r\n", &CONTENTS[..20]).await;
+        pkg.url = format!("http://{address}/UP0000-BLUS00000_00-PS3SHORTTRANSFR-A0100-V0100.pkg");
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(32);
+        let result = pkg.ensure_complete(tx, &pkg_path, config).await;
+        assert!(result.is_err());
+        assert!(!pkg_path.exists());
+
+        let _ = std::fs::remove_dir_all(pkg_path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn download_resumes_partial_file() {
+        const CONTENTS: &[u8] = b"rusty-psn resume fixture";
+
+        let address = serve_once_with_status("206 Partial Content", "Content-Type: application/octet-stream\r\nContent-Range: bytes 10-23/24\r\n", &CONTENTS[10..]).await;
+
+        let (pkg, folder) = download_fixture(address, "UP0000-TEST00005_00-RESUMEFIXTURE000-A0100-V0100.pkg", CONTENTS, "17ac53ebf14a90abe996ad3a04736c36a1e4e9ab", "rusty-psn-resume-test");
+
+        let pkg_path = folder.join(pkg.file_name().unwrap());
+        std::fs::write(&pkg_path, &CONTENTS[..10]).unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(32);
+        let result = pkg.ensure_complete(tx, &pkg_path, super::DownloadConfig::default()).await;
+
+        if let Err(e) = result {
+            panic!("Failed to resume download: {:?}", e);
+        }
+
+        assert!(matches!(rx.recv().await, Some(super::DownloadStatus::Resuming(10))));
+        assert_eq!(std::fs::read(&pkg_path).unwrap(), CONTENTS);
+
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+
+    #[tokio::test]
+    async fn download_resumes_killed_transfer() {
+        const CONTENTS: &[u8] = b"rusty-psn killed transfer fixture";
+
+        let address = serve_once_with_status("206 Partial Content", "Content-Type: application/octet-stream\r\nContent-Range: bytes 12-32/33\r\n", &CONTENTS[12..]).await;
+
+        let (pkg, folder) = download_fixture(address, "UP0000-TEST00011_00-KILLEDTRANSFER00-A0100-V0100.pkg", CONTENTS, "abba7fd6acb54cfa0ac6fb8cc0e672d16e63efbd", "rusty-psn-killed-transfer-test");
+
+        // What a killed download leaves behind: the preallocated temp file, with zeroes after what was received.
+        let pkg_path = folder.join(pkg.file_name().unwrap());
+        let mut leftover = CONTENTS[..12].to_vec();
+        leftover.resize(CONTENTS.len(), 0);
+        std::fs::write(crate::utils::temp_pkg_path(&pkg_path), &leftover).unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(32);
+        let result = pkg.ensure_complete(tx, &pkg_path, super::DownloadConfig::default()).await;
+
+        if let Err(e) = result {
+            panic!("Failed to resume a killed download: {:?}", e);
+        }
+
+        let mut resumed_from = None;
+        while let Ok(status) = rx.try_recv() {
+            if let super::DownloadStatus::Resuming(offset) = status {
+                resumed_from = Some(offset);
+            }
+        }
+
+        assert_eq!(resumed_from, Some(12));
+        assert_eq!(std::fs::read(&pkg_path).unwrap(), CONTENTS);
+
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+
+    #[tokio::test]
+    async fn killed_download_is_partial() {
+        const CONTENTS: &[u8] = b"rusty-psn preallocated file fixture";
+
+        // Nothing listens on this port, so skipping the check is the only way ensure_complete succeeds.
+        let address = unreachable_address();
+        let (pkg, folder) = download_fixture(address, "UP0000-TEST00012_00-PREALLOCATED0000-A0100-V0100.pkg", CONTENTS, "f384bc3139970b8e92b2e2e477a20921c9c1fb94", "rusty-psn-killed-download-test");
+
+        let pkg_path = folder.join(pkg.file_name().unwrap());
+        let mut leftover = CONTENTS[..20].to_vec();
+        leftover.resize(CONTENTS.len(), 0);
+
+        std::fs::write(crate::utils::temp_pkg_path(&pkg_path), &leftover).unwrap();
+        assert!(matches!(pkg.check_file(&pkg_path, super::Sha1Backend::default()).await, Ok(super::PkgFileStatus::Partial { size: 20 })));
+
+        // Older versions downloaded straight into the final path.
+        std::fs::rename(crate::utils::temp_pkg_path(&pkg_path), &pkg_path).unwrap();
+        assert!(matches!(pkg.check_file(&pkg_path, super::Sha1Backend::default()).await, Ok(super::PkgFileStatus::Partial { size: 20 })));
+
+        let config = super::DownloadConfig { skip_verify_existing: true, ..Default::default() };
+        let (tx, _rx) = tokio::sync::mpsc::channel(32);
+        assert!(pkg.ensure_complete(tx, &pkg_path, config).await.is_err());
+
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+
+    #[tokio::test]
+    async fn ensure_complete_keeps_valid_file() {
+        const CONTENTS: &[u8] = b"rusty-psn ensure complete fixture";
+
+        // Nothing listens on this port, so the check fails if it tries to download the pkg again.
+        let address = unreachable_address();
+        let (pkg, folder) = download_fixture(address, "UP0000-TEST00003_00-ENSURECOMPLETE00-A0100-V0100.pkg", CONTENTS, "2b2d83dad1a05cfdfa61b82eadaaf854ac6956ab", "rusty-psn-ensure-complete-test");
+
+        let pkg_path = folder.join(pkg.file_name().unwrap());
+        std::fs::write(&pkg_path, CONTENTS).unwrap();
+
+        for _ in 0..2 {
+            let (tx, _rx) = tokio::sync::mpsc::channel(32);
+            let result = pkg.ensure_complete(tx, &pkg_path, super::DownloadConfig::default()).await;
+
+            if let Err(e) = result {
+                panic!("A complete pkg was considered incomplete: {:?}", e);
+            }
+        }
+
+        // A corrupted file has to be downloaded again, which fails here.
+        std::fs::write(&pkg_path, b"corrupted").unwrap();
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(32);
+        let result = pkg.ensure_complete(tx, &pkg_path, super::DownloadConfig::default()).await;
+        assert!(matches!(result, Err(super::DownloadError::Reqwest(_))));
+
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+
+    #[tokio::test]
+    async fn pre_checked_file_isnt_hashed_again() {
+        const CONTENTS: &[u8] = b"rusty-psn pre-checked fixture";
+
+        let address = serve_once("Content-Type: application/octet-stream\r\n", CONTENTS).await;
+        let (pkg, folder) = download_fixture(address, "UP0000-TEST00013_00-PRECHECKED000000-A0100-V0100.pkg", CONTENTS, "b0a85799575167cec7a13e1cd89d7a9db66c4ea7", "rusty-psn-pre-checked-test");
+
+        let pkg_path = folder.join(pkg.file_name().unwrap());
+        std::fs::write(&pkg_path, [0xAA; CONTENTS.len()]).unwrap();
+
+        let config = super::DownloadConfig { existing_status: Some(super::PkgFileStatus::Mismatched), ..Default::default() };
+        let (tx, mut rx) = tokio::sync::mpsc::channel(32);
+
+        if let Err(e) = pkg.ensure_complete(tx, &pkg_path, config).await {
+            panic!("Failed to download over a pre-checked file: {:?}", e);
+        }
+
+        // The only verification is the one for the new data, once it's downloaded.
+        let mut statuses = Vec::new();
+        while let Ok(status) = rx.try_recv() {
+            statuses.push(status);
+        }
+
+        assert_eq!(statuses.iter().filter(| s | matches!(s, super::DownloadStatus::Verifying)).count(), 1);
+        assert!(!matches!(statuses.first(), Some(super::DownloadStatus::Verifying)));
+        assert_eq!(std::fs::read(&pkg_path).unwrap(), CONTENTS);
+
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    fn update_summary() {
+        let mut info = super::UpdateInfo::empty(super::PlaformVariant::PS3);
+        info.title_id = String::from("BLUS30443");
+
+        for (version, size) in [("01.01", 1000), ("01.05", 500), ("01.02", 24)] {
+            let mut pkg = super::PackageInfo::empty();
+            pkg.version = String::from(version);
+            pkg.size = size;
+            info.packages.push(pkg);
+        }
+
+        let summary = info.summary();
+        assert_eq!(summary.package_count, 3);
+        assert_eq!(summary.total_size, 1524);
+        assert_eq!(summary.latest_version.as_deref(), Some("01.05"));
+        assert_eq!(summary.to_string(), format!("[PS3] BLUS30443 (3 update(s) - {} total)", bytesize::ByteSize::b(1524)));
+
+        info.titles.push(String::from("Demon's Souls"));
+        assert!(info.summary().to_string().starts_with("[PS3] BLUS30443 - Demon's Souls ("));
+    }
+
+    #[tokio::test]
+    async fn quarantine_failed_download() {
+        const CONTENTS: &[u8] = b"rusty-psn quarantine fixture";
+
+        let address = serve_once("Content-Type: application/octet-stream\r\n", CONTENTS).await;
+
+        let (pkg, folder) = download_fixture(address, "UP0000-TEST00004_00-QUARANTINEFIXTUR-A0100-V0100.pkg", CONTENTS, "da39a3ee5e6b4b0d3255bfef95601890afd80709", "rusty-psn-quarantine-test");
+        let pkg_path = folder.join(pkg.file_name().unwrap());
+
+        let config = super::DownloadConfig { quarantine_failed: true, ..Default::default() };
+        let (tx, _rx) = tokio::sync::mpsc::channel(32);
+        let result = pkg.ensure_complete(tx, &pkg_path, config).await;
+
+        assert!(matches!(result, Err(super::DownloadError::HashMismatch { short_on_data: false, .. })));
+        assert!(result.unwrap_err().to_string().contains("expected da39a3ee5e6b4b0d3255bfef95601890afd80709, got "));
+        assert!(!pkg_path.exists());
+
+        let quarantined: Vec<_> = std::fs::read_dir(folder.join("failed")).unwrap().map(| e | e.unwrap().path()).collect();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(std::fs::read(&quarantined[0]).unwrap(), CONTENTS);
+
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+
+    #[tokio::test]
+    async fn pre_check_looks_in_the_title_folder() {
+        const CONTENTS: &[u8] = b"rusty-psn title folder fixture";
+
+        let address = unreachable_address();
+        let (pkg, download_path) = download_fixture(address, "UP0000-CUSA00000_00-VERIFYEXISTINGFX-A0100-V0100.pkg", CONTENTS, "97acf0a3474d472b1f30afd2bb00b109661f7626", "rusty-psn-verify-existing-test");
+        let config = super::DownloadConfig::default();
+
+        let (path, status) = super::pre_check_package(&pkg, &download_path, "CUSA00000", "Verify Test", &config).await.unwrap();
+        assert_eq!(status, super::PkgFileStatus::Missing);
+        assert_eq!(path, pkg.file_path(&download_path, "CUSA00000", "Verify Test", false));
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        std::fs::write(&path, CONTENTS).unwrap();
+        let (_, status) = super::pre_check_package(&pkg, &download_path, "CUSA00000", "Verify Test", &config).await.unwrap();
+        assert_eq!(status, super::PkgFileStatus::Verified);
+
+        std::fs::write(&path, b"rusty-psn title folder fixtur!").unwrap();
+        let (_, status) = super::pre_check_package(&pkg, &download_path, "CUSA00000", "Verify Test", &config).await.unwrap();
+        assert_eq!(status, super::PkgFileStatus::Mismatched);
+
+        let _ = std::fs::remove_dir_all(&download_path);
+    }
+
+    #[test]
+    fn serial_list_parsing() {
+        let list = "# PS3\nBLUS-30443\n\n  npub30826  \nBLUS30443\n# CUSA00000\n";
+        assert_eq!(super::parse_serial_list(list), vec![String::from("BLUS30443"), String::from("NPUB30826")]);
+        assert!(super::parse_serial_list("\n# nothing here\n").is_empty());
+    }
+
+    #[test]
+    fn manifest_lists_packages() {
+        let folder = std::env::temp_dir().join("rusty-psn-manifest-test");
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+
+        let mut info = super::UpdateInfo::empty(super::PlaformVariant::PS4);
+        info.title_id = String::from("CUSA00000");
+        info.titles.push(String::from("Manifest Test"));
+
+        let mut pkg = super::PackageInfo::empty();
+        pkg.url = String::from("http://localhost/UP0000-CUSA00000_00-MANIFESTFIXTURE0-A0100-V0100_0.pkg");
+        pkg.version = String::from("01.00");
+        pkg.size = 100;
+        pkg.sha1sum = String::from("48f89ee10e9b13d9b4fdcabaf6a1469719f1879f");
+        pkg.part_number = Some(1);
+        info.packages.push(pkg.clone());
+
+        pkg.url = String::from("http://localhost/UP0000-CUSA00000_00-MANIFESTFIXTURE0-A0100-V0100_1.pkg");
+        pkg.part_number = Some(2);
+        pkg.offset = 100;
+        info.packages.push(pkg);
+
+        let digest = "e39cfd9df643169ff58bbb36b5d88db9700ed14e3a4ec77447351847cb2e96ec";
+        info.package_digests.insert(String::from("01.00"), String::from(digest));
+
+        let manifest_path = folder.join("manifest.json");
+        super::save_manifest(&manifest_path, &[info]).unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&manifest_path).unwrap()).unwrap();
+        let title = &json[0];
+        assert_eq!(title["title_id"], "CUSA00000");
+        assert_eq!(title["title"], "Manifest Test");
+        assert_eq!(title["platform"], "PS4");
+
+        let pkg = &title["packages"][0];
+        assert_eq!(pkg["url"], "http://localhost/UP0000-CUSA00000_00-MANIFESTFIXTURE0-A0100-V0100_0.pkg");
+        assert_eq!(pkg["version"], "01.00");
+        assert_eq!(pkg["sha1sum"], "48f89ee10e9b13d9b4fdcabaf6a1469719f1879f");
+        assert_eq!(pkg["size"], 100);
+        assert_eq!(pkg["part_number"], 1);
+        assert_eq!(title["package_digests"]["01.00"], digest);
+
+        let loaded = super::load_manifest(&manifest_path).unwrap();
+        assert_eq!(loaded[0].title_id, "CUSA00000");
+        assert_eq!(loaded[0].title(), "Manifest Test");
+        assert!(loaded[0].platform_variant == super::PlaformVariant::PS4);
+        assert_eq!(loaded[0].packages[0].sha1sum, "48f89ee10e9b13d9b4fdcabaf6a1469719f1879f");
+        assert_eq!(loaded[0].packages[0].part_number, Some(1));
+        assert_eq!(loaded[0].packages[1].offset, 100);
+        assert_eq!(loaded[0].titles.len(), 1);
+        assert_eq!(loaded[0].package_digests.get("01.00").map(String::as_str), Some(digest));
+
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+
+    #[tokio::test]
+    async fn plan_round_trip_and_status() {
+        let folder = std::env::temp_dir().join("rusty-psn-plan-test");
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+
+        let mut info = super::UpdateInfo::empty(super::PlaformVariant::PS4);
+        info.title_id = String::from("CUSA00000");
+
+        let mut pkg = super::PackageInfo::empty();
+        pkg.url = String::from("http://localhost/UP0000-CUSA00000_00-PLANSTATUSFIXTUR-A0100-V0100.pkg");
+        pkg.version = String::from("01.00");
+        pkg.size = 26;
+        pkg.sha1sum = String::from("48f89ee10e9b13d9b4fdcabaf6a1469719f1879f");
+        pkg.hash_whole_file = true;
+        info.packages.push(pkg);
+
+        let plan_path = folder.join("plan.json");
+        super::save_plan(&plan_path, &[info]).unwrap();
+
+        let plan = super::load_plan(&plan_path).unwrap();
+        let pkg = &plan[0].packages[0];
+        assert_eq!(plan[0].title_id, "CUSA00000");
+
+        let pkg_path = folder.join(pkg.file_name().unwrap());
+        let backend = crate::utils::Sha1Backend::default();
+        assert_eq!(pkg.check_file(&pkg_path, backend).await.unwrap(), super::PkgFileStatus::Missing);
+
+        std::fs::write(&pkg_path, b"rusty-psn").unwrap();
+        assert_eq!(pkg.check_file(&pkg_path, backend).await.unwrap(), super::PkgFileStatus::Partial { size: 9 });
+
+        std::fs::write(&pkg_path, b"rusty-psn plan fixture....").unwrap();
+        assert_eq!(pkg.check_file(&pkg_path, backend).await.unwrap(), super::PkgFileStatus::Verified);
+
+        std::fs::write(&pkg_path, b"rusty-psn plan fixture!!!!").unwrap();
+        assert_eq!(pkg.check_file(&pkg_path, backend).await.unwrap(), super::PkgFileStatus::Mismatched);
+
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    fn duplicate_packages() {
+        let mut updates = Vec::new();
+
+        for (serial, sha1sum) in [("BLUS30443", "aa"), ("BLES00932", "AA"), ("BLJM60220", "bb")] {
+            let mut info = super::UpdateInfo::empty(super::PlaformVariant::PS3);
+            info.title_id = String::from(serial);
+
+            let mut pkg = super::PackageInfo::empty();
+            pkg.version = String::from("01.00");
+            pkg.sha1sum = String::from(sha1sum);
+            info.packages.push(pkg);
+
+            updates.push(info);
+        }
+
+        let duplicates = super::find_duplicate_packages(&updates);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates["aa"].len(), 2);
+    }
+
+    #[test]
+    fn version_filter() {
+        let mut pkg = super::PackageInfo::empty();
+        pkg.version = String::from("01.05");
+
+        let filter = super::VersionFilter {
+            allowlist: vec![String::from("BLUS30443:01.0*")],
+            blocklist: vec![String::from("NPUB30826:01.05")]
+        };
+
+        assert!(filter.allows("BLUS30443", &pkg));
+        assert!(filter.allows("BCES00001", &pkg));
+        assert!(!filter.allows("NPUB30826", &pkg));
+
+        pkg.version = String::from("02.00");
+        assert!(!filter.allows("BLUS30443", &pkg));
+    }
+
+    #[test]
+    fn hash_bytes_round_trip() {
+        let mut pkg = super::PackageInfo::empty();
+        pkg.sha1sum = String::from("da39a3ee5e6b4b0d3255bfef95601890afd80709");
+
+        match pkg.hash_bytes() {
+            Some(bytes) => assert_eq!(hex::encode(bytes), pkg.sha1sum),
+            None => panic!("Failed to parse a valid SHA-1 hash")
+        }
+
+        pkg.sha1sum = String::from("da39a3ee");
+        assert!(pkg.hash_bytes().is_none());
+
+        // A SHA-1 hash isn't valid for a SHA-256 package, and the other way around.
+        pkg.sha1sum = String::from("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert!(pkg.hash_bytes().is_none());
+
+        pkg.hash_algorithm = super::HashAlgorithm::Sha256;
+        assert_eq!(pkg.hash_bytes().map(| bytes | bytes.len()), Some(32));
+    }
+
+    #[tokio::test]
+    async fn verify_external_folder() {
+        const CONTENTS: &[u8] = b"rusty-psn hash backend fixture";
+
+        let dir = std::env::temp_dir().join("rusty-psn-verify-folder-test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let package = | name: &str, version: &str | {
+            let mut pkg = super::PackageInfo::empty();
+            pkg.url = format!("http://example.com/{name}");
+            pkg.version = String::from(version);
+            pkg.sha1sum = String::from("d906d2a313b97623ef094cbdc08f053dbb8e76d8");
+            pkg.hash_whole_file = true;
+            pkg
+        };
+
+        let mut info = super::UpdateInfo::empty(super::utils::PlaformVariant::PS4);
+        info.title_id = String::from("CUSA00000");
+        info.packages = vec![package("good.pkg", "01.00"), package("bad.pkg", "01.01"), package("missing.pkg", "01.02")];
+
+        std::fs::write(dir.join("good.pkg"), CONTENTS).unwrap();
+        std::fs::write(dir.join("bad.pkg"), b"not the right data").unwrap();
+        std::fs::write(dir.join("extra.pkg"), CONTENTS).unwrap();
+
+        let result = info.verify_folder(&dir, crate::utils::Sha1Backend::default()).await.unwrap();
+
+        assert_eq!(result.verified, vec!["01.00"]);
+        assert_eq!(result.mismatched, vec!["01.01"]);
+        assert_eq!(result.missing, vec!["01.02"]);
+        assert_eq!(result.unmatched_files, vec![dir.join("extra.pkg")]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn copy_pkg_file_writes_all_bytes() {
+        let dir = std::env::temp_dir().join("rusty-psn-copy-pkg-test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let first_part = dir.join("part_0.pkg");
+        let second_part = dir.join("part_1.pkg");
+        let merged = dir.join("merged.pkg");
+
+        std::fs::write(&first_part, [0x11; 1000]).unwrap();
+        std::fs::write(&second_part, [0x22; 500]).unwrap();
+
+        let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(10);
+
+        let mut copied = super::utils::copy_pkg_file(&first_part, &merged, 0, Some(progress_tx)).await.unwrap();
+        copied += super::utils::copy_pkg_file(&second_part, &merged, 1000, None).await.unwrap();
+
+        assert_eq!(copied, 1500);
+        assert_eq!(progress_rx.recv().await, Some(1000));
+        assert_eq!(progress_rx.recv().await, None);
+        assert_eq!(std::fs::metadata(&merged).unwrap().len(), copied);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn merge_parts_through_scratch_folder() {
+        let base = std::env::temp_dir().join("rusty-psn-merge-scratch-test");
+        let _ = std::fs::remove_dir_all(&base);
+
+        let download_path = base.join("pkgs");
+        let scratch_path = base.join("scratch");
+
+        let mut info = super::UpdateInfo::empty(super::PlaformVariant::PS4);
+        info.title_id = String::from("CUSA00000");
+        info.titles.push(String::from("Merge Test"));
+
+        let title_path = crate::utils::create_new_pkg_path(&download_path, &info.title_id, &info.title(), false);
+        std::fs::create_dir_all(&title_path).unwrap();
+
+        for (idx, contents) in [&[0x11; 100][..], &[0x22; 50][..]].iter().enumerate() {
+            let mut pkg = super::PackageInfo::empty();
+            pkg.url = format!("http://localhost/UP0000-CUSA00000_00-MERGESCRATCHTEST-A0100-V0100_{idx}.pkg");
+            pkg.version = String::from("01.00");
+            pkg.offset = idx as u64 * 100;
+            pkg.part_number = Some(idx + 1);
+
+            std::fs::write(title_path.join(pkg.file_name().unwrap()), contents).unwrap();
+            info.packages.push(pkg);
+        }
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(10);
+        let result = info.merge_parts(tx, &download_path, false, super::DEFAULT_MERGED_NAME_TEMPLATE, Some(&scratch_path), false).await;
+
+        if let Err(e) = result {
+            panic!("Failed to merge parts: {:?}", e);
+        }
+
+        let merged = std::fs::read(title_path.join("UP0000-CUSA00000_00-MERGESCRATCHTEST-A0100-V0100.pkg")).unwrap();
+        assert_eq!(merged.len(), 150);
+        assert!(merged[..100].iter().all(| b | *b == 0x11) && merged[100..].iter().all(| b | *b == 0x22));
+
+        // Nothing is left behind in the scratch folder once the merged file is moved.
+        assert_eq!(std::fs::read_dir(&scratch_path).unwrap().count(), 0);
+
+        // Merging again checks the result against the manifest's digest, when there's one.
+        info.package_digests.insert(String::from("01.00"), String::from("e39cfd9df643169ff58bbb36b5d88db9700ed14e3a4ec77447351847cb2e96ec"));
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(10);
+        if let Err(e) = info.merge_parts(tx, &download_path, false, super::DEFAULT_MERGED_NAME_TEMPLATE, Some(&scratch_path), false).await {
+            panic!("Merged file didn't match its digest: {:?}", e);
+        }
+
+        info.package_digests.insert(String::from("01.00"), String::from("0000000000000000000000000000000000000000000000000000000000000000"));
+
+        // Parts are kept around when the merged file turns out wrong.
+        let (tx, _rx) = tokio::sync::mpsc::channel(10);
+        let result = info.merge_parts(tx, &download_path, false, super::DEFAULT_MERGED_NAME_TEMPLATE, Some(&scratch_path), true).await;
+        assert!(matches!(result, Err(super::MergeError::MergedFileMismatch { .. })));
+        assert!(info.packages.iter().all(| pkg | title_path.join(pkg.file_name().unwrap()).exists()));
+
+        info.package_digests.clear();
+
+        // Without a digest there's no telling whether the merged file is right, so the parts stay too.
+        let (tx, _rx) = tokio::sync::mpsc::channel(10);
+        if let Err(e) = info.merge_parts(tx, &download_path, false, super::DEFAULT_MERGED_NAME_TEMPLATE, Some(&scratch_path), true).await {
+            panic!("Failed to merge parts: {:?}", e);
+        }
+
+        assert!(info.packages.iter().all(| pkg | title_path.join(pkg.file_name().unwrap()).exists()));
+
+        info.package_digests.insert(String::from("01.00"), String::from("e39cfd9df643169ff58bbb36b5d88db9700ed14e3a4ec77447351847cb2e96ec"));
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(10);
+        if let Err(e) = info.merge_parts(tx, &download_path, false, super::DEFAULT_MERGED_NAME_TEMPLATE, Some(&scratch_path), true).await {
+            panic!("Failed to merge parts: {:?}", e);
+        }
+
+        assert!(info.packages.iter().all(| pkg | !title_path.join(pkg.file_name().unwrap()).exists()));
+        assert!(title_path.join("UP0000-CUSA00000_00-MERGESCRATCHTEST-A0100-V0100.pkg").exists());
+
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn validate_merge_parts() {
+        let mut info = super::UpdateInfo::empty(super::PlaformVariant::PS4);
+
+        for (version, part_number) in [("01.00", 1), ("01.00", 2), ("01.01", 1)] {
+            let mut pkg = super::PackageInfo::empty();
+            pkg.version = String::from(version);
+            pkg.part_number = Some(part_number);
+            info.packages.push(pkg);
+        }
+
+        assert!(info.validate_parts().is_ok());
+
+        info.packages[1].part_number = Some(1);
+        assert!(matches!(info.validate_parts(), Err(super::MergeError::DuplicatePart(1))));
+
+        info.packages[1].part_number = Some(3);
+        assert!(matches!(info.validate_parts(), Err(super::MergeError::MissingPart(2))));
+    }
+
+    #[test]
+    fn merged_file_names() {
+        let original = "UP0000-CUSA00000_00-0000000000000000-A0101-V0100";
+
+        assert_eq!(super::render_merged_file_name(super::DEFAULT_MERGED_NAME_TEMPLATE, "CUSA00000", "01.01", original, false), format!("{original}.pkg"));
+        assert_eq!(super::render_merged_file_name("{serial}_{version}.pkg", "CUSA00000", "01.01", original, true), "CUSA00000_01.01.pkg");
+        assert_eq!(super::render_merged_file_name("{content_id}.pkg", "CUSA00000", "01.01", original, false), "UP0000-CUSA00000_00-0000000000000000.pkg");
+        assert_eq!(super::render_merged_file_name("{content_id}.pkg", "CUSA00000", "01.01", original, true), "UP0000-CUSA00000_00-0000000000000000_01.01.pkg");
+        assert_eq!(super::render_merged_file_name("../{serial}.pkg", "CUSA00000", "01.01", original, false), ".._CUSA00000.pkg");
+    }
+
+    #[test]
+    fn short_transfer_detection() {
+        let mut pkg = super::PackageInfo::empty();
+        pkg.size = 0x1000;
+
+        // Vita and PSP pkgs also end with a hash suffix, but their declared sizes are exact.
+        assert!(!pkg.is_short_transfer(0x1000));
+        assert!(pkg.is_short_transfer(0x1000 - 0x20));
+
+        // PS3 pkg whose declared size might count the hash suffix.
+        pkg.size_may_skip_suffix = true;
+        assert!(!pkg.is_short_transfer(0x1000));
+        assert!(!pkg.is_short_transfer(0x1000 - 0x20));
+        assert!(pkg.is_short_transfer(0x1000 - 0x21));
+    }
+
+    #[test]
+    fn retry_delay_backoff() {
+        assert_eq!(super::retry_delay(1).as_secs(), 2);
+        assert_eq!(super::retry_delay(3).as_secs(), 8);
+        assert_eq!(super::retry_delay(6).as_secs(), 60);
+        assert_eq!(super::retry_delay(100).as_secs(), 60);
+    }
+
+    #[test]
+    fn proxy_url_validation() {
+        assert!(super::client::validate_proxy_url("http://127.0.0.1:8080").is_ok());
+        assert!(super::client::validate_proxy_url("socks5://127.0.0.1:1080").is_ok());
+        assert!(super::client::validate_proxy_url("not a proxy").is_err());
+    }
+
+    #[test]
+    fn disk_space_buffer() {
+        assert_eq!(super::with_space_buffer(0), 0);
+        assert_eq!(super::with_space_buffer(1000), 1050);
+        assert_eq!(super::with_space_buffer(u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn regional_variants() {
+        let variants = super::utils::get_regional_variants("BLUS30443");
+        assert_eq!(variants, vec!["BLES30443", "BLJM30443", "BLAS30443", "BLKS30443"]);
+
+        let variants = super::utils::get_regional_variants("NPEB00123");
+        assert!(variants.contains(&String::from("NPUB00123")));
+        assert!(!variants.contains(&String::from("NPEB00123")));
+
+        assert!(super::utils::get_regional_variants("CUSA00001").is_empty());
+        assert!(super::utils::get_regional_variants("BLUS").is_empty());
+    }
+
+    // For the tests that query Sony's servers.
+    fn live_client() -> super::client::ReqwestClient {
+        super::client::ReqwestClient::new(&super::client::ClientConfig::default()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn parse_ac3() {
+        match super::UpdateInfo::get_info(&live_client(), "NPUB30826".to_string()).await {
+            Ok(info) => assert!(info.packages.len() == 1),
+            Err(e) => panic!("Failed to get info for NPUB30826: {:?}", e)
+        }
+    }
+
+    #[tokio::test]
+    async fn parse_lpb() {
+        match super::UpdateInfo::get_info(&live_client(), "BCUS98148".to_string()).await {
+            Ok(info) => assert!(info.packages.len() == 13),
+            Err(e) => panic!("Failed to get info for BCUS98148: {:?}", e)
+        }
+    }
+
+    #[tokio::test]
+    async fn parse_infamous2() {
+        match super::UpdateInfo::get_info(&live_client(), "NPUA80638".to_string()).await {
+            Ok(info) => assert!(info.packages.len() == 3),
+            Err(e) => panic!("Failed to get info for NPUA80638: {:?}", e)
+        }
+    }
+    
+    #[tokio::test]
+    async fn parse_tokyo_jungle() {
+        match super::UpdateInfo::get_info(&live_client(), "NPUA80523".to_string()).await {
+            Ok(info) => assert!(info.packages.len() == 1),
+            Err(e) => panic!("Failed to get info for NPUA80523: {:?}", e)
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_waits_once_the_bucket_is_empty() {
+        let limiter = super::client::RateLimiter::new(10_000);
+        let started_at = std::time::Instant::now();
+
+        // The first second's worth goes through right away.
+        limiter.acquire(10_000).await;
+        assert!(started_at.elapsed() < std::time::Duration::from_millis(100));
+
+        limiter.acquire(2_000).await;
+        assert!(started_at.elapsed() >= std::time::Duration::from_millis(150));
+    }
+
+    #[tokio::test]
+    async fn client_reuses_its_connection() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use super::client::PsnClient;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let accepted = std::sync::Arc::new(AtomicUsize::new(0));
+
+        // Answers every request on a connection until the client closes it, counting the connections.
+        let server_accepted = accepted.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                server_accepted.fetch_add(1, Ordering::SeqCst);
+
+                tokio::spawn(async move {
+                    let mut request = [0; 4096];
+
+                    while let Ok(read) = socket.read(&mut request).await {
+                        if read == 0 || socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok").await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        let client = super::client::ReqwestClient::new(&super::client::ClientConfig::default()).unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(client.get_text(&format!("http://{address}/")).await.unwrap(), "ok");
+        }
+
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,5 +1,5 @@
 use quick_xml::Reader;
-use quick_xml::events::Event;
+use quick_xml::events::{BytesStart, Event};
 
 use super::{PackageInfo, UpdateInfo};
 
@@ -44,7 +44,8 @@ pub fn parse_response(response: String, info: &mut UpdateInfo) -> Result<(), Par
                             }
                         }
                     }
-                    b"package" => {
+                    // Some regional variants use hybrid_package elements, with the same attributes.
+                    b"package" | b"hybrid_package" => {
                         for attribute in e.attributes().filter_map(| a | a.ok()) {
                             match attribute.key.as_ref() {
                                 b"version" => {
@@ -75,6 +76,12 @@ pub fn parse_response(response: String, info: &mut UpdateInfo) -> Result<(), Par
                                         last.url = value.to_string();
                                     }
                                 }
+                                b"ps3_system_ver" => {
+                                    if let Some(last) = info.packages.last_mut() {
+                                        let value = attribute.unescape_value().map_err(ParseError::XmlParsing)?;
+                                        last.required_firmware = Some(value.to_string());
+                                    }
+                                }
                                 b"manifest_url" => {
                                     if let Some(last) = info.packages.last_mut() {
                                         let value = attribute.unescape_value().map_err(ParseError::XmlParsing)?;
@@ -87,6 +94,10 @@ pub fn parse_response(response: String, info: &mut UpdateInfo) -> Result<(), Par
                             }
                         }
                     }
+                    // Patch notes for the package it's nested in.
+                    b"changeinfo" => {
+                        parse_changeinfo(&e, info)?;
+                    }
                     b"Error" => {
                         err_encountered = true;
                     }
@@ -112,7 +123,11 @@ pub fn parse_response(response: String, info: &mut UpdateInfo) -> Result<(), Par
                 depth -= 1;
             }
             Ok(Event::Empty(e)) => {
-                if let b"package" = e.name().as_ref() {
+                if e.name().as_ref() == b"changeinfo" {
+                    parse_changeinfo(&e, info)?;
+                }
+
+                if let b"package" | b"hybrid_package" = e.name().as_ref() {
                     for attribute in e.attributes().filter_map(| a | a.ok()) {
                         match attribute.key.as_ref() {
                             b"version" => {
@@ -143,6 +158,12 @@ pub fn parse_response(response: String, info: &mut UpdateInfo) -> Result<(), Par
                                     last.url = value.to_string();
                                 }
                             }
+                            b"ps3_system_ver" => {
+                                if let Some(last) = info.packages.last_mut() {
+                                    let value = attribute.unescape_value().map_err(ParseError::XmlParsing)?;
+                                    last.required_firmware = Some(value.to_string());
+                                }
+                            }
                             _ => {
 
                             }
@@ -176,3 +197,95 @@ pub fn parse_response(response: String, info: &mut UpdateInfo) -> Result<(), Par
 
     Ok(())
 }
+
+fn parse_changeinfo(element: &BytesStart, info: &mut UpdateInfo) -> Result<(), ParseError> {
+    for attribute in element.attributes().filter_map(| a | a.ok()) {
+        if attribute.key.as_ref() == b"url" {
+            if let Some(last) = info.packages.last_mut() {
+                let value = attribute.unescape_value().map_err(ParseError::XmlParsing)?;
+                last.changeinfo_url = value.to_string();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::psn::utils::PlaformVariant;
+
+    #[test]
+    fn parse_hybrid_package() {
+        let response = r#"<?xml version="1.0" encoding="UTF-8"?>
+<titlepatch titleid="BLAS50000">
+<tag name="BLAS50000_T2" popup="true" signoff="true">
+<hybrid_package version="01.01" size="1024" sha1sum="da39a3ee5e6b4b0d3255bfef95601890afd80709" url="http://b0.ww.np.dl.playstation.net/tppkg/np/BLAS50000/BLAS50000_T2/HP0000-BLAS50000_00-HYBRIDPACKAGE000-A0101-V0100-PE.pkg">
+<paramsfo><TITLE>Hybrid Title</TITLE></paramsfo>
+</hybrid_package>
+<hybrid_package version="01.02" size="2048" sha1sum="da39a3ee5e6b4b0d3255bfef95601890afd80709" url="http://b0.ww.np.dl.playstation.net/tppkg/np/BLAS50000/BLAS50000_T2/HP0000-BLAS50000_00-HYBRIDPACKAGE000-A0102-V0100-PE.pkg" ps3_system_ver="04.8500"/>
+</tag>
+</titlepatch>"#;
+
+        let mut info = UpdateInfo::empty(PlaformVariant::PS3);
+        if let Err(e) = parse_response(response.to_string(), &mut info) {
+            panic!("Failed to parse response: {:?}", e);
+        }
+
+        assert_eq!(info.title_id, "BLAS50000");
+        assert_eq!(info.titles, vec![String::from("Hybrid Title")]);
+        assert_eq!(info.packages.len(), 2);
+        assert_eq!(info.packages[0].version, "01.01");
+        assert_eq!(info.packages[1].size, 2048);
+        assert_eq!(info.packages[0].required_firmware, None);
+        assert_eq!(info.packages[1].firmware_version().as_deref(), Some("4.85"));
+    }
+
+    #[test]
+    fn parse_psp_response() {
+        // Same layout as the -ver.xml files served for PSP titles, which don't list a sha1sum for their packages.
+        let response = r#"<?xml version="1.0" encoding="UTF-8"?>
+<titlepatch titleid="NPUG80318">
+<tag name="NPUG80318_T1" popup="true" signoff="true">
+<package version="01.01" size="10528" url="http://b0.ww.np.dl.playstation.net/tppkg/np/NPUG80318/NPUG80318_T1/UP9000-NPUG80318_00-PATAPON200000000-A0101-V0100-PE.pkg">
+<paramsfo><TITLE>Patapon 2</TITLE></paramsfo>
+</package>
+</tag>
+</titlepatch>"#;
+
+        let mut info = UpdateInfo::empty(PlaformVariant::PSP);
+        if let Err(e) = parse_response(response.to_string(), &mut info) {
+            panic!("Failed to parse response: {:?}", e);
+        }
+
+        assert_eq!(info.title_id, "NPUG80318");
+        assert_eq!(info.titles, vec![String::from("Patapon 2")]);
+        assert_eq!(info.packages.len(), 1);
+        assert_eq!(info.packages[0].version, "01.01");
+        assert_eq!(info.packages[0].size, 10528);
+        assert!(info.packages[0].sha1sum.is_empty());
+        assert!(info.packages[0].hash_bytes().is_none());
+        assert_eq!(info.packages[0].file_name().as_deref(), Some("UP9000-NPUG80318_00-PATAPON200000000-A0101-V0100-PE.pkg"));
+    }
+
+    #[test]
+    fn parse_changeinfo_url() {
+        let response = r#"<?xml version="1.0" encoding="UTF-8"?>
+<titlepatch titleid="CUSA00000">
+<tag name="" mandatory="true">
+<package version="01.01" size="1024" manifest_url="http://example.com/CUSA00000-A0101.json">
+<changeinfo url="http://example.com/CUSA00000/changeinfo/changeinfo.xml"/>
+</package>
+</tag>
+</titlepatch>"#;
+
+        let mut info = UpdateInfo::empty(PlaformVariant::PS4);
+        if let Err(e) = parse_response(response.to_string(), &mut info) {
+            panic!("Failed to parse response: {:?}", e);
+        }
+
+        assert_eq!(info.packages.len(), 1);
+        assert_eq!(info.packages[0].changeinfo_url, "http://example.com/CUSA00000/changeinfo/changeinfo.xml");
+    }
+}
@@ -0,0 +1,224 @@
+use crate::psn::UpdateError;
+
+use core::str;
+use std::{fmt, io::{Error, SeekFrom}, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::{fs::OpenOptions, io::{AsyncBufReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, BufWriter}, sync::mpsc::Sender};
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum PlaformVariant {
+    #[cfg_attr(feature = "clap", value(name = "PS3"))]
+    PS3,
+    #[cfg_attr(feature = "clap", value(name = "PS4"))]
+    PS4,
+    #[cfg_attr(feature = "clap", value(name = "PSVita", alias = "PSV"))]
+    PSV,
+    #[cfg_attr(feature = "clap", value(name = "PSP"))]
+    PSP
+}
+
+impl PlaformVariant {
+    pub const ALL: [PlaformVariant; 4] = [PlaformVariant::PS3, PlaformVariant::PS4, PlaformVariant::PSV, PlaformVariant::PSP];
+}
+
+impl fmt::Display for PlaformVariant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlaformVariant::PSV => write!(f, "PSVita"),
+            _ => write!(f, "{:?}", self)
+        }
+    }
+}
+
+// Digital PSP titles, then UMD ones.
+const PSP_PREFIXES: [&str; 18] = [
+    "NPUG", "NPEG", "NPJG", "NPHG", "NPUH", "NPEH", "NPJH", "NPHH",
+    "UCUS", "ULUS", "UCES", "ULES", "UCJS", "ULJS", "ULJM", "UCAS", "ULAS", "ULKS"
+];
+
+pub fn get_platform_variant(title_id: &str) -> Option<PlaformVariant> {
+    // Digital PSP serials start with NP too, so they have to be told apart from PS3 ones first.
+    if PSP_PREFIXES.iter().any(|&prefix| { title_id.starts_with(prefix) }) {
+        return Some(PlaformVariant::PSP);
+    }
+
+    if ["NP", "BL", "BC"].iter().any(|&prefix| { title_id.starts_with(prefix) }) {
+        return Some(PlaformVariant::PS3);
+    }
+
+    if title_id.starts_with("CUSA") {
+        return Some(PlaformVariant::PS4);
+    }
+
+    if ["PCSA", "PCSB", "PCSC", "PCSD", "PCSE", "PCSF", "PCSG", "PCSH", "PCSI", "VCAS"].iter().any(|&prefix| { title_id.starts_with(prefix) }) {
+        return Some(PlaformVariant::PSV);
+    }
+
+    return None
+}
+
+// PS3 serial prefixes that only differ in the region they were released for.
+// US, EU, JP, Asia and Korea, in that order where the region has an equivalent.
+const PS3_REGIONAL_PREFIXES: [&[&str]; 4] = [
+    &["BLUS", "BLES", "BLJM", "BLAS", "BLKS"],
+    &["BCUS", "BCES", "BCJS", "BCAS", "BCKS"],
+    &["NPUB", "NPEB", "NPJB", "NPHB"],
+    &["NPUA", "NPEA", "NPJA", "NPHA"],
+];
+
+// Generates the serials of the same title number in the other regions, for example
+// BLUS30443 -> BLES30443, BLJM30443... The base serial isn't included.
+// PS4 serials are shared across regions, so they don't have any variants.
+pub fn get_regional_variants(title_id: &str) -> Vec<String> {
+    if get_platform_variant(title_id) != Some(PlaformVariant::PS3) || title_id.len() <= 4 || !title_id.is_char_boundary(4) {
+        return Vec::new();
+    }
+
+    let (prefix, number) = title_id.split_at(4);
+
+    PS3_REGIONAL_PREFIXES.iter()
+        .find(| group | group.contains(&prefix))
+        .map(| group | {
+            group.iter()
+                .filter(| p | **p != prefix)
+                .map(| p | format!("{p}{number}"))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn get_update_info_url(title_id: &str, platform_variant: PlaformVariant) -> Result<String, UpdateError> {
+    match platform_variant {
+        PlaformVariant::PS3 => {
+            Ok(format!("https://a0.ww.np.dl.playstation.net/tpl/np/{0}/{0}-ver.xml", title_id))
+        },
+        PlaformVariant::PS4 => {
+            let key = match hex::decode("AD62E37F905E06BC19593142281C112CEC0E7EC3E97EFDCAEFCDBAAFA6378D84") {
+                Ok(key) => key,
+                Err(_) => return Err(UpdateError::InvalidSerial),
+            };
+            let msg = format!("np_{0}", title_id);
+            let mut hasher = match HmacSha256::new_from_slice(&key) {
+                Ok(hasher) => hasher,
+                Err(_) => return Err(UpdateError::InvalidSerial)
+            };
+
+            hasher.update(msg.as_ref());
+            let hash_bytes = hasher.finalize().into_bytes();
+
+            Ok(format!("https://gs-sec.ww.np.dl.playstation.net/plo/np/{0}/{1:x}/{0}-ver.xml", title_id, hash_bytes))
+        }
+        // Same scheme as PS4, with its own key and path.
+        PlaformVariant::PSV => {
+            let key = match hex::decode("E5E278AA1EE34082A088279C83F9BBC806821C52F2AB5D2B4ABD995450355114") {
+                Ok(key) => key,
+                Err(_) => return Err(UpdateError::InvalidSerial),
+            };
+            let msg = format!("np_{0}", title_id);
+            let mut hasher = match HmacSha256::new_from_slice(&key) {
+                Ok(hasher) => hasher,
+                Err(_) => return Err(UpdateError::InvalidSerial)
+            };
+
+            hasher.update(msg.as_ref());
+            let hash_bytes = hasher.finalize().into_bytes();
+
+            Ok(format!("https://gs-sec.ww.np.dl.playstation.net/pl/np/{0}/{1:x}/{0}-ver.xml", title_id, hash_bytes))
+        }
+        PlaformVariant::PSP => {
+            Ok(format!("http://fu01.psn.np.dl.playstation.net/pl/np/{0}/{0}-ver.xml", title_id))
+        }
+    }
+}
+
+const MERGE_CHUNK_SIZE: usize = 1024 * 1024 * 128;
+// Copies src_path into target_path starting at offset. If progress_tx is set, the bytes copied
+// so far are sent after every chunk. Nobody listening anymore doesn't stop the copy.
+pub async fn copy_pkg_file(src_path: &PathBuf, target_path: &PathBuf, offset: u64, progress_tx: Option<Sender<u64>>) -> Result<u64, Error> {
+    let src_file = OpenOptions::default()
+        .create(false)
+        .read(true)
+        .write(false)
+        .open(src_path)
+        .await?;
+
+    let mut target_file = OpenOptions::default()
+        .create(true)
+        .read(false)
+        .write(true)
+        .open(target_path)
+        .await?;
+
+
+    if offset > 0 {
+        target_file.seek(SeekFrom::Start(offset)).await?;
+    }
+
+    let mut writer = BufWriter::with_capacity(MERGE_CHUNK_SIZE, target_file);
+    let mut reader = BufReader::with_capacity(MERGE_CHUNK_SIZE, src_file);
+    let mut read_bytes = 0;
+
+    loop {
+        let chunk = reader.fill_buf().await?;
+        if chunk.is_empty() {
+            break;
+        }
+
+        let chunk_len = chunk.len();
+        writer.write_all(chunk).await?;
+        reader.consume(chunk_len);
+        read_bytes += chunk_len as u64;
+
+        if let Some(tx) = progress_tx.as_ref() {
+            let _ = tx.send(read_bytes).await;
+        }
+    }
+
+    // Make sure the data is actually on disk before reporting the part as merged,
+    // instead of relying on the writer getting flushed when it's dropped.
+    writer.flush().await?;
+    writer.into_inner().sync_all().await?;
+
+    Ok(read_bytes)
+}
+
+// Moves a file that failed verification into a failed/ folder next to target_path, the path it was meant to end up at.
+// It's named after it, prefixed with the current time, so what was actually received can be looked at later.
+// Returns where the file ended up.
+pub async fn quarantine_file(path: &Path, target_path: &Path) -> Result<PathBuf, Error> {
+    let file_name = target_path.file_name()
+        .ok_or_else(|| Error::new(std::io::ErrorKind::InvalidInput, "path has no file name"))?
+        .to_string_lossy()
+        .to_string()
+    ;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(| d | d.as_secs())
+        .unwrap_or(0)
+    ;
+
+    let mut quarantine_path = target_path.with_file_name("failed");
+    tokio::fs::create_dir_all(&quarantine_path).await?;
+    quarantine_path.push(format!("{timestamp}-{file_name}"));
+
+    move_file(path, &quarantine_path).await?;
+    Ok(quarantine_path)
+}
+
+// Moves a file into its final location. Renaming doesn't work across volumes,
+// in which case the file gets copied over and the original removed.
+pub async fn move_file(src_path: &Path, target_path: &Path) -> Result<(), Error> {
+    if tokio::fs::rename(src_path, target_path).await.is_ok() {
+        return Ok(());
+    }
+
+    tokio::fs::copy(src_path, target_path).await?;
+    tokio::fs::remove_file(src_path).await
+}
\ No newline at end of file
@@ -0,0 +1,439 @@
+use std::convert::TryInto;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytesize::ByteSize;
+use sha1::Digest;
+use serde::{Deserialize, Serialize};
+
+use tokio::fs;
+use tokio::fs::{File, OpenOptions};
+
+use tokio::io::{self, AsyncBufReadExt, AsyncReadExt, BufReader, AsyncSeekExt, SeekFrom};
+
+use crate::psn::DownloadError;
+
+const WINDOWS_INVALID_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+#[cfg(target_family = "windows")]
+const INVALID_CHARS: &[char] = &WINDOWS_INVALID_CHARS;
+
+#[cfg(target_family = "unix")]
+const INVALID_CHARS: &[char] = &['/'];
+
+// With strict, names are kept valid on Windows regardless of the host OS, for folders that are shared
+// with Windows machines (eg. a network share).
+fn sanitize_title(title: &str, strict: bool) -> String {
+    //replace invalid characters with underscores or anything we want lol
+    let sanitized = title.replace(| c: char | INVALID_CHARS.contains(&c) || (strict && WINDOWS_INVALID_CHARS.contains(&c)), "_");
+
+    // Windows also drops trailing dots and spaces from names, so the folder wouldn't be found again.
+    if strict {
+        sanitized.trim_end_matches(| c: char | c == '.' || c == ' ').to_string()
+    }
+    else {
+        sanitized
+    }
+}
+
+// Environment variable with the download folder to use when none was set through the frontends.
+pub const DOWNLOAD_DIR_ENV_VAR: &str = "RUSTY_PSN_DOWNLOAD_DIR";
+
+pub fn default_download_path() -> PathBuf {
+    match std::env::var_os(DOWNLOAD_DIR_ENV_VAR) {
+        Some(path) if !path.is_empty() => PathBuf::from(path),
+        _ => PathBuf::from("pkgs/")
+    }
+}
+
+fn create_old_pkg_path(download_path: &PathBuf, serial: &str) -> PathBuf {
+    let mut target_path = download_path.clone();
+    target_path.push(serial);
+    target_path
+}
+
+pub fn create_new_pkg_path(download_path: &PathBuf, serial: &str, title: &str, strict_names: bool) -> PathBuf {
+    let mut target_path = download_path.clone();
+    let sanitized_title = sanitize_title(title, strict_names);
+    target_path.push(format!("{} - {}", serial, sanitized_title));
+    target_path
+}
+
+pub async fn create_pkg_file(download_path: PathBuf, serial: &str, title: &str, pkg_name: &str, strict_names: bool) -> Result<File, DownloadError> {
+    let target_path = create_pkg_path(download_path, serial, title, pkg_name, strict_names).await;
+    open_pkg_file(&target_path).await
+}
+
+// Path of a file in the title's folder. Only works out the path, nothing on disk is created or moved.
+pub fn resolve_pkg_path(download_path: &PathBuf, serial: &str, title: &str, pkg_name: &str, strict_names: bool) -> PathBuf {
+    let mut target_path = create_new_pkg_path(download_path, serial, title, strict_names);
+    target_path.push(pkg_name);
+    target_path
+}
+
+// Pkgs are downloaded into a file with this suffix next to their final path,
+// and only get their real name once they've been verified.
+pub const TEMP_FILE_SUFFIX: &str = ".rustypsn.tmp";
+
+pub fn temp_pkg_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().map(| name | name.to_os_string()).unwrap_or_default();
+    file_name.push(TEMP_FILE_SUFFIX);
+    path.with_file_name(file_name)
+}
+
+// Where the data in a file ends, leaving out any zeroes at the end. Downloads preallocate their file, so one that got killed
+// midway leaves it at full size with a zeroed tail, and this is where it can pick up from. Data that really ends in zeroes
+// only means a few more bytes get downloaded again.
+pub async fn data_length(file: &mut File) -> io::Result<u64> {
+    let mut end = file.metadata().await?.len();
+    let mut buffer = vec![0; 64 * 1024];
+
+    while end > 0 {
+        let start = end.saturating_sub(buffer.len() as u64);
+        let chunk = &mut buffer[..(end - start) as usize];
+
+        file.seek(SeekFrom::Start(start)).await?;
+        file.read_exact(chunk).await?;
+
+        if let Some(last) = chunk.iter().rposition(| b | *b != 0) {
+            return Ok(start + last as u64 + 1);
+        }
+
+        end = start;
+    }
+
+    Ok(0)
+}
+
+// Path of a file in the title's folder, moving a folder with the old name format over to the current one if needed.
+pub async fn create_pkg_path(download_path: PathBuf, serial: &str, title: &str, pkg_name: &str, strict_names: bool) -> PathBuf {
+    let target_path = create_new_pkg_path(&download_path, serial, &title, strict_names);
+
+    // Check for the old path format.
+    let old_path = create_old_pkg_path(&download_path, serial);
+    if old_path.exists() {
+        info!("Found a folder with the old name format, trying to rename to current one.");
+
+        if let Err(e) = fs::rename(&old_path, &target_path).await {
+            error!("Failed to rename folder: {e}");
+        }
+    }
+    
+    resolve_pkg_path(&download_path, serial, title, pkg_name, strict_names)
+}
+
+pub async fn open_pkg_file(target_path: &Path) -> Result<File, DownloadError> {
+    info!("Creating file for pkg at path {:?}", target_path);
+
+    if let Some(parent) = target_path.parent() {
+        match fs::create_dir_all(parent).await {
+            Ok(_) => info!("Created directory for updates"),
+            Err(e) => {
+                match e.kind() {
+                    io::ErrorKind::AlreadyExists => {},
+                    _ => return Err(DownloadError::IoWithPath { error: e, path: parent.to_path_buf() }),
+                }
+            }
+        }
+    } else {
+        let error = io::Error::new(io::ErrorKind::Other, "Target path has no parent directory");
+        return Err(DownloadError::IoWithPath { error, path: target_path.to_path_buf() });
+    }
+
+    // Using OpenOptions to avoid the file getting truncated if it already exists
+    // .create(true) preserves an existing file's contents.
+    let pkg_file = OpenOptions::default()
+        .create(true)
+        .read(true)
+        .write(true)
+        .open(target_path)
+        .await
+    ;
+
+    pkg_file.map_err(| error | DownloadError::IoWithPath { error, path: target_path.to_path_buf() })
+}
+
+// State of the generator behind jittered. Zero until it's seeded on first use.
+static JITTER_STATE: AtomicU64 = AtomicU64::new(0);
+
+// xorshift64, which is plenty for spreading out delays. The jitter doesn't need to be unpredictable, only evenly spread.
+fn xorshift(mut state: u64) -> u64 {
+    if state == 0 {
+        state = SystemTime::now().duration_since(UNIX_EPOCH).map(| d | d.as_nanos() as u64).unwrap_or(0) | 1;
+    }
+
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    state
+}
+
+// A number in [0, 1), from the shared generator.
+fn jitter_fraction() -> f64 {
+    let previous = JITTER_STATE.fetch_update(Ordering::Relaxed, Ordering::Relaxed, | state | Some(xorshift(state))).unwrap_or(0);
+    (xorshift(previous) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// Adds up to 50% on top of the delay, so requests spaced out by it don't line up in a fixed rhythm.
+pub fn jittered(delay: Duration) -> Duration {
+    delay + delay.mul_f64(jitter_fraction() / 2.0)
+}
+
+// Eg. "12.3 MB/s – 1m 23s remaining".
+pub fn format_speed(bytes_per_sec: u64, eta_secs: Option<u64>) -> String {
+    let eta = match eta_secs {
+        Some(secs) if secs >= 3600 => format!("{}h {}m remaining", secs / 3600, (secs % 3600) / 60),
+        Some(secs) if secs >= 60 => format!("{}m {}s remaining", secs / 60, secs % 60),
+        Some(secs) => format!("{secs}s remaining"),
+        None => String::from("calculating...")
+    };
+
+    format!("{}/s – {eta}", ByteSize::b(bytes_per_sec))
+}
+
+// How long ago something happened, given the seconds since then. Eg. "5 minutes ago".
+pub fn format_age(secs: u64) -> String {
+    let (amount, unit) = match secs {
+        secs if secs < 60 => return String::from("just now"),
+        secs if secs < 3600 => (secs / 60, "minute"),
+        secs if secs < 86400 => (secs / 3600, "hour"),
+        secs => (secs / 86400, "day")
+    };
+
+    if amount == 1 {
+        format!("1 {unit} ago")
+    } else {
+        format!("{amount} {unit}s ago")
+    }
+}
+
+// Free space on the disk that holds the given path.
+// The target folder might not exist yet, so the closest parent that does is checked instead.
+pub fn available_space(path: &Path) -> io::Result<u64> {
+    let existing_path = path
+        .ancestors()
+        .find(| p | p.exists())
+        .unwrap_or_else(|| Path::new("."))
+    ;
+
+    fs2::available_space(existing_path)
+}
+
+// SHA-1 implementation used to verify pkgs.
+// Smol is the portable pure Rust implementation that has always been used. Accelerated uses the SHA extensions
+// of x86 CPUs when they're available, it stays opt-in until it's been measured against Smol on real pkgs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum Sha1Backend {
+    #[default]
+    Smol,
+    Accelerated
+}
+
+// Hash that Sony lists for a pkg. Everything uses SHA-1 for now, SHA-256 is there in case newer endpoints switch to it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha1,
+    Sha256
+}
+
+impl HashAlgorithm {
+    // Works out the algorithm from the length of a hex hash. Returns None for lengths that don't match any.
+    pub fn from_hex_len(len: usize) -> Option<HashAlgorithm> {
+        match len {
+            40 => Some(HashAlgorithm::Sha1),
+            64 => Some(HashAlgorithm::Sha256),
+            _ => None
+        }
+    }
+
+    // Size of the digest, in bytes.
+    pub fn digest_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha1 => 20,
+            HashAlgorithm::Sha256 => 32
+        }
+    }
+}
+
+impl std::fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            HashAlgorithm::Sha1 => write!(f, "SHA-1"),
+            HashAlgorithm::Sha256 => write!(f, "SHA-256")
+        }
+    }
+}
+
+enum FileHasher {
+    Smol(sha1_smol::Sha1),
+    Accelerated(sha1::Sha1),
+    Sha256(sha2::Sha256)
+}
+
+impl FileHasher {
+    // The backend only matters for SHA-1.
+    fn new(algorithm: HashAlgorithm, backend: Sha1Backend) -> FileHasher {
+        match (algorithm, backend) {
+            (HashAlgorithm::Sha1, Sha1Backend::Smol) => FileHasher::Smol(sha1_smol::Sha1::new()),
+            (HashAlgorithm::Sha1, Sha1Backend::Accelerated) => FileHasher::Accelerated(sha1::Sha1::new()),
+            (HashAlgorithm::Sha256, _) => FileHasher::Sha256(sha2::Sha256::new())
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            FileHasher::Smol(hasher) => hasher.update(data),
+            FileHasher::Accelerated(hasher) => Digest::update(hasher, data),
+            FileHasher::Sha256(hasher) => Digest::update(hasher, data)
+        }
+    }
+
+    fn digest(self) -> Vec<u8> {
+        match self {
+            FileHasher::Smol(hasher) => hasher.digest().bytes().to_vec(),
+            FileHasher::Accelerated(hasher) => hasher.finalize().to_vec(),
+            FileHasher::Sha256(hasher) => hasher.finalize().to_vec()
+        }
+    }
+}
+
+// Same as hash_file, for when the file isn't open yet.
+pub async fn hash_file_at(path: &Path, hash: &[u8], hash_whole_file: bool, algorithm: HashAlgorithm, backend: Sha1Backend) -> Result<bool, DownloadError> {
+    let mut file = File::open(path).await.map_err(| error | DownloadError::IoWithPath { error, path: path.to_path_buf() })?;
+    hash_file(&mut file, hash, hash_whole_file, algorithm, backend).await
+}
+
+const CHUNK_SIZE: usize = 1024 * 1024 * 128;
+pub async fn hash_file(file: &mut File, hash: &[u8], hash_whole_file: bool, algorithm: HashAlgorithm, backend: Sha1Backend) -> Result<bool, DownloadError> {
+    // If the file size is below the length of the embedded sha1-hash suffix,
+    // don't bother hashing the contents. Download's borked.
+    let file_length = file.metadata().await.map_err(DownloadError::Tokio)?.len();
+    if file_length <= suffix_size(hash_whole_file) {
+        return Ok(false);
+    }
+
+    Ok(compute_file_hash(file, hash_whole_file, algorithm, backend).await? == hash)
+}
+
+// Last 0x20 bytes are the SHA1 hash for PS3 updates. PS4 updates don't include hash suffix.
+fn suffix_size(hash_whole_file: bool) -> u64 {
+    if hash_whole_file { 0 } else { 0x20 }
+}
+
+// Returns the file's raw digest, to show what was actually received when it doesn't match.
+pub async fn compute_file_hash(file: &mut File, hash_whole_file: bool, algorithm: HashAlgorithm, backend: Sha1Backend) -> Result<Vec<u8>, DownloadError> {
+    let mut hasher = FileHasher::new(algorithm, backend);
+
+    let file_length = file.metadata().await.map_err(DownloadError::Tokio)?.len();
+    let file_length_without_suffix: usize = file_length.saturating_sub(suffix_size(hash_whole_file))
+        .try_into()
+        .map_err(|_| DownloadError::Tokio(io::Error::new(io::ErrorKind::InvalidData, "file is too big to be hashed")))?;
+
+    // Write operations during the download move the internal seek pointer.
+    // Resetting it to 0 makes reader actually read the whole thing.
+    file.seek(SeekFrom::Start(0)).await.map_err(DownloadError::Tokio)?;
+
+    let mut reader = BufReader::with_capacity(CHUNK_SIZE, file);
+    let mut processed_length = 0;
+    while processed_length < file_length_without_suffix {
+        let chunk_buffer = reader.fill_buf().await.map_err(DownloadError::Tokio)?;
+        let chunk_length = chunk_buffer.len();
+        if chunk_length == 0 {
+            break;
+        }
+        
+        let previously_processed_length: usize = processed_length;
+        processed_length = processed_length + chunk_length;
+        // While iterating through the file a chunk being processed may already include some hash suffix bits which should not be hashed.
+        // In such case file chunk is stripped of those extra suffix bits.
+        let hashable_buffer = if processed_length > file_length_without_suffix {
+            &chunk_buffer[..file_length_without_suffix - previously_processed_length]
+        } else {
+            &chunk_buffer
+        };
+
+        hasher.update(&hashable_buffer);
+        reader.consume(chunk_length);
+    }
+
+    Ok(hasher.digest())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strict_title_sanitization() {
+        let title = "Ratchet & Clank: Quest for Booty...";
+
+        assert_eq!(sanitize_title(title, true), "Ratchet & Clank_ Quest for Booty");
+        assert_eq!(sanitize_title("A/B", false), "A_B");
+    }
+
+    #[test]
+    fn jitter_is_spread_out() {
+        let delay = Duration::from_millis(1000);
+        let samples: Vec<Duration> = (0..1000).map(| _ | jittered(delay)).collect();
+
+        assert!(samples.iter().all(| s | *s >= delay && *s < delay + delay / 2));
+
+        // Split the jitter range in ten buckets, every one of them should get a fair share.
+        let mut buckets = [0; 10];
+        for sample in samples.iter() {
+            let bucket = ((*sample - delay).as_secs_f64() / 0.05) as usize;
+            buckets[bucket.min(9)] += 1;
+        }
+
+        assert!(buckets.iter().all(| count | *count > 50), "uneven jitter: {:?}", buckets);
+    }
+
+    #[test]
+    fn speed_formatting() {
+        assert_eq!(format_speed(0, None), "0 B/s – calculating...");
+        assert!(format_speed(2048, Some(83)).ends_with("/s – 1m 23s remaining"));
+        assert!(format_speed(2048, Some(3725)).ends_with("/s – 1h 2m remaining"));
+        assert!(format_speed(2048, Some(5)).ends_with("/s – 5s remaining"));
+    }
+
+    #[test]
+    fn age_formatting() {
+        assert_eq!(format_age(5), "just now");
+        assert_eq!(format_age(60), "1 minute ago");
+        assert_eq!(format_age(7199), "1 hour ago");
+        assert_eq!(format_age(3 * 86400 + 10), "3 days ago");
+    }
+
+    #[test]
+    fn temp_path_is_a_sibling() {
+        let path = PathBuf::from("pkgs/BCUS98174 - Uncharted/BCUS98174_01.00.pkg");
+        assert_eq!(temp_pkg_path(&path), PathBuf::from("pkgs/BCUS98174 - Uncharted/BCUS98174_01.00.pkg.rustypsn.tmp"));
+    }
+
+    #[tokio::test]
+    async fn hash_backends_match() {
+        let expected = hex::decode("d906d2a313b97623ef094cbdc08f053dbb8e76d8").unwrap();
+
+        // PS3 pkgs end with their own 0x20 bytes hash, which isn't part of the hashed data.
+        let mut contents = b"rusty-psn hash backend fixture".to_vec();
+        contents.extend_from_slice(&[0xFF; 0x20]);
+
+        let path = std::env::temp_dir().join("rusty-psn-hash-backends-test.pkg");
+        std::fs::write(&path, &contents).unwrap();
+
+        for backend in [Sha1Backend::Smol, Sha1Backend::Accelerated] {
+            let mut file = File::open(&path).await.unwrap();
+            assert!(hash_file(&mut file, &expected, false, HashAlgorithm::Sha1, backend).await.unwrap(), "{:?} backend produced a different digest", backend);
+            assert_eq!(compute_file_hash(&mut file, false, HashAlgorithm::Sha1, backend).await.unwrap(), expected);
+        }
+
+        let mut file = File::open(&path).await.unwrap();
+        let sha256 = compute_file_hash(&mut file, false, HashAlgorithm::Sha256, Sha1Backend::default()).await.unwrap();
+        assert_eq!(hex::encode(sha256), "54bfa0c2bcdc0a5f8931686803547415505d9881fc5b46b5e221d9d3a61e128f");
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,83 +1,328 @@
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 use bytesize::ByteSize;
 use poll_promise::Promise;
+use serde::Serialize;
 use tokio::runtime::Runtime;
 use crossterm::{cursor, terminal};
+use crossterm::style::Stylize;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 
 use crate::psn::*;
+use crate::psn::client::{validate_proxy_url, ClientConfig, PoolConfig, RateLimiter, ReqwestClient};
+use crate::utils::{format_speed, Sha1Backend};
 use crate::Args;
 
+// How many more times a search is attempted if it fails with an error worth retrying.
+const SEARCH_RETRIES: usize = 2;
+
+// Exit codes for when something went wrong along the way. If several things did, the highest one is used.
+const EXIT_SEARCH_FAILED: i32 = 2;
+const EXIT_DOWNLOAD_FAILED: i32 = 3;
+const EXIT_MERGE_FAILED: i32 = 4;
+// Used by --verify-only, for files that are wrong or incomplete and for files that aren't there at all.
+const EXIT_VERIFY_MISMATCH: i32 = 5;
+const EXIT_VERIFY_MISSING: i32 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum DownloadOutcome {
+    Downloaded,
+    // Found complete on disk by --resume, so it wasn't downloaded again.
+    AlreadyComplete,
+    Failed,
+    // Not picked, excluded by the version filter, or left out after an earlier failure.
+    Skipped
+}
+
+#[derive(Serialize)]
+struct PackageReport<'a> {
+    #[serde(flatten)]
+    package: &'a PackageInfo,
+    status: DownloadOutcome
+}
+
+// Where a package would be downloaded to, for --dry-run.
+#[derive(Serialize)]
+pub struct DownloadPlan {
+    pub path: PathBuf,
+    pub size: u64,
+    pub url: String,
+    // The file is already there, complete and with the right hash, so it wouldn't be downloaded again.
+    pub verified: bool
+}
+
+#[derive(Serialize)]
+struct TitleReport<'a> {
+    title_id: &'a str,
+    title: String,
+    platform_variant: utils::PlaformVariant,
+    packages: Vec<PackageReport<'a>>
+}
+
 pub fn start_app(args: Args) {
     let runtime = Runtime::new().unwrap();
 
     let _guard = runtime.enter();
 
-    let titles = args.titles[0].split(' ');
-    let silent_mode = args.silent;
-    let destination_path = args.destination_path.unwrap_or_else(|| PathBuf::from("pkgs/"));
+    if let Some(plan_path) = args.status {
+        let destination_path = args.destination_path.unwrap_or_else(crate::utils::default_download_path);
+        report_plan_status(&runtime, &plan_path, &destination_path, args.sha1_backend, args.strict_file_names);
+        return;
+    }
+
+    let output_format = if args.json { OutputFormat::Json } else { args.format };
+    // Machine-readable output goes to stdout once everything is done, so nothing else can be printed there
+    // in the meantime. Errors go to stderr instead, and there's no one to answer prompts.
+    let structured_output = output_format != OutputFormat::Text;
+
+    // Inline serials are separated by spaces, the ones in the file by newlines. Empty when downloading from a manifest.
+    let mut serial_list = args.titles.first().map(| t | t.replace(' ', "\n")).unwrap_or_default();
+
+    if let Some(titles_file) = args.titles_file.as_ref() {
+        match std::fs::read_to_string(titles_file) {
+            Ok(contents) => {
+                serial_list.push('\n');
+                serial_list.push_str(&contents);
+            }
+            Err(e) => {
+                error!("Failed to read serials from {:?}: {e}", titles_file);
+                print_error(structured_output, &format!("Failed to read the serials file {}: {e}.", titles_file.display()));
+                return;
+            }
+        }
+    }
+
+    let titles = parse_serial_list(&serial_list);
+    let silent_mode = args.silent || structured_output;
+    let stop_on_first_error = args.stop_on_first_error;
+    let merge = args.merge;
+    let save_changeinfo = args.save_changeinfo;
+    let resume = args.resume;
+    let verify_folder = args.verify_folder.clone();
+    let destination_path = args.destination_path.unwrap_or_else(crate::utils::default_download_path);
 
     if silent_mode {
         info!("App started in silent mode!");
     }
 
-    let update_info = {
-        let mut info = Vec::new();
+    if let Some(proxy) = args.proxy.as_deref() {
+        if let Err(e) = validate_proxy_url(proxy) {
+            error!("Invalid proxy URL {proxy}: {e}");
+            print_error(structured_output, &format!("The proxy URL {proxy} isn't valid, it should look like http://host:port or socks5://host:port ({e})."));
+            return;
+        }
+    }
+
+    // Protects against accidentally starting hundreds of searches (and downloads) at once.
+    if titles.len() > args.max_titles && !args.yes {
+        warn!("{} serials were provided, over the limit of {}", titles.len(), args.max_titles);
 
-        let promises = titles
-            .into_iter()
-            .map(| t | (t.to_string(), Promise::spawn_async(UpdateInfo::get_info(t.to_string()))))
-            .collect::<Vec<(String, Promise<Result<UpdateInfo, UpdateError>>)>>()
-        ;
+        if silent_mode || !std::io::stdin().is_terminal() {
+            error!("Not processing {} serials without confirmation", titles.len());
+            print_error(structured_output, &format!("{} serials were provided, which is over the limit of {}. Use --yes to process them anyway, or raise --max-titles.", titles.len(), args.max_titles));
+            return;
+        }
 
-        if !silent_mode {
-            println!("Searching for updates...\n");
+        println!("{} serials were provided, which is over the limit of {}. Continue? [y/N]", titles.len(), args.max_titles);
+
+        let mut response = String::new();
+        std::io::stdin().read_line(&mut response).unwrap();
+
+        if !response.trim().eq_ignore_ascii_case("y") {
+            info!("User declined processing {} serials", titles.len());
+            return;
         }
+    }
+
+    // The pause gives time to read the results before the screen is cleared, which only matters when someone is watching.
+    let pause_between = {
+        let default_pause = if silent_mode || !std::io::stdout().is_terminal() { 0 } else { 3 };
+        Duration::from_secs(args.pause_between.unwrap_or(default_pause))
+    };
 
-        for (id, promise) in promises {
-            info!("Checking in on search promises");
+    let pool_config = PoolConfig {
+        max_idle_per_host: args.pool_max_idle_per_host,
+        idle_timeout: args.pool_idle_timeout.map(Duration::from_secs)
+    };
+
+    let download_config = DownloadConfig {
+        min_free_space: ByteSize::gb(args.min_free_space).as_u64(),
+        skip_verify_existing: args.no_verify_existing,
+        connect_timeout: Some(Duration::from_secs(args.connect_timeout)),
+        transfer_timeout: Some(Duration::from_secs(args.request_timeout)),
+        sha1_backend: args.sha1_backend,
+        pool: pool_config,
+        proxy: args.proxy.clone(),
+        max_retries: args.max_retries,
+        quarantine_failed: args.quarantine_failed,
+        strict_file_names: args.strict_file_names,
+        rate_limit: args.max_rate.map(| kb_per_sec | Arc::new(RateLimiter::new(ByteSize::kb(kb_per_sec).as_u64()))),
+        existing_status: None,
+        client: None
+    };
+
+    let version_filter = VersionFilter {
+        allowlist: args.version_allowlist,
+        blocklist: args.version_blocklist
+    };
+
+    let client_config = ClientConfig {
+        cookies: args.cookies,
+        connect_timeout: Some(Duration::from_secs(args.connect_timeout)),
+        request_timeout: Some(Duration::from_secs(args.request_timeout)),
+        pool: pool_config,
+        proxy: args.proxy,
+        request_delay: Duration::from_millis(args.request_delay_ms)
+    };
+
+    let mut downloads_started = 0;
+
+    let client = match ReqwestClient::new(&client_config) {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to create http client: {e}");
+            print_error(structured_output, &format!("Failed to create the http client: {e}."));
+            return;
+        }
+    };
 
-            match promise.block_and_take() {
-                Ok(i) => {
-                    info!("Successfully search for updates for {id}");
-                    info.push(i);
+    // Every download goes through the same client, so connections to the CDN can be reused between parts.
+    let download_config = match download_config.with_shared_client() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Failed to create download client: {e}");
+            print_error(structured_output, &format!("Failed to create the http client: {e}."));
+            return;
+        }
+    };
+
+    let mut search_failed = false;
+
+    let mut update_info = match args.from_manifest.as_deref() {
+        Some(manifest_path) => {
+            match load_manifest(manifest_path) {
+                Ok(info) => {
+                    info!("Loaded {} title(s) from manifest {:?}", info.len(), manifest_path);
+                    info
                 }
                 Err(e) => {
-                    match e {
-                        UpdateError::UnhandledErrorResponse(e) => {
-                            error!("Unexpected error received in response from PSN: {e}");
-                            println!("{id}: PSN returned an unexpected error: {e}.");
-                        }
-                        UpdateError::InvalidSerial => {
-                            error!("Invalid serial for updates query {id}");
-                            println!("{id}: The provided serial didn't give any results, double-check your input.");
-                        }
-                        UpdateError::NoUpdatesAvailable => {
-                            warn!("No updates available for serial {id}");
-                            println!("{id}: The provided serial doesn't have any available updates.");
-                        }
-                        UpdateError::Reqwest(e) => {
-                            error!("reqwest error on updates query: {e}");
-                            println!("{id}: There was an error on the request: {e}.");
-                        }
-                        UpdateError::XmlParsing(e) => {
-                            error!("Failed to deserialize response for {id}: {e}");
-                            println!("{id}: Error parsing response from PSN, try again later ({e}).");
-                        }
-                        UpdateError::ManifestParsing(e) => {
-                            error!("Failed to deserialize manifest response for {id}: {e}");
-                            println!("{id}: Error parsing manifest response from PSN, try again later ({e}).");
-                        }
-                    }
+                    error!("Failed to load manifest {:?}: {e}", manifest_path);
+                    print_error(structured_output, &format!("Failed to load the manifest {}: {e}.", manifest_path.display()));
+                    return;
                 }
             }
         }
+        None => {
+            // Without a connection every search would fail on its own, so check once and give a single clear error instead.
+            if !runtime.block_on(client.is_online()) {
+                error!("Connectivity probe failed, not starting searches");
+                print_error(structured_output, "No network connection detected. Check your connection and try again.");
+                return;
+            }
 
-        info
+            let (info, failed) = search_titles(&client, titles, silent_mode, structured_output);
+            search_failed = failed;
+            info
+        }
     };
 
-    for update in update_info {
+    if let Some(platform) = args.filter_platform {
+        let found = update_info.len();
+        update_info.retain(| update | update.matches_platform(platform));
+
+        info!("Kept {} of {found} title(s) for platform {platform}", update_info.len());
+
+        if found > 0 && update_info.is_empty() {
+            if !silent_mode {
+                println!("None of the titles that were found are for {platform}, there's nothing to download.");
+            }
+
+            if search_failed {
+                info!("Exiting with code {EXIT_SEARCH_FAILED}");
+                std::process::exit(EXIT_SEARCH_FAILED);
+            }
+
+            return;
+        }
+    }
+
+    if let Some(plan_path) = args.save_plan {
+        match save_plan(&plan_path, &update_info) {
+            Ok(()) => info!("Saved plan for {} title(s) to {:?}", update_info.len(), plan_path),
+            Err(e) => {
+                error!("Failed to save plan to {:?}: {e}", plan_path);
+                print_error(structured_output, &format!("Failed to save the plan to {}: {e}.", plan_path.display()));
+            }
+        }
+    }
+
+    if let Some(manifest_path) = args.output_manifest {
+        match save_manifest(&manifest_path, &update_info) {
+            Ok(()) => info!("Saved manifest for {} title(s) to {:?}", update_info.len(), manifest_path),
+            Err(e) => {
+                error!("Failed to save manifest to {:?}: {e}", manifest_path);
+                print_error(structured_output, &format!("Failed to save the manifest to {}: {e}.", manifest_path.display()));
+            }
+        }
+    }
+
+    if let Some(folder) = verify_folder {
+        verify_existing_folder(&runtime, &update_info, &folder, download_config.sha1_backend);
+        return;
+    }
+
+    if args.dry_run {
+        let plans = update_info.iter()
+            .map(| update | {
+                let mut update = update.clone();
+                let title_id = update.title_id.clone();
+                update.packages.retain(| pkg | version_filter.allows(&title_id, pkg));
+
+                let plan = runtime.block_on(plan_download(&update, &destination_path, &download_config));
+                (update, plan)
+            })
+            .collect::<Vec<(UpdateInfo, Vec<DownloadPlan>)>>()
+        ;
+
+        if let Err(e) = print_dry_run(&plans, output_format, args.silent) {
+            error!("Failed to print the dry run: {e}");
+        }
+
+        return;
+    }
+
+    if args.verify_only {
+        let exit_code = verify_downloaded(&runtime, &update_info, &destination_path, &download_config, silent_mode);
+        let exit_code = if search_failed { exit_code.max(EXIT_SEARCH_FAILED) } else { exit_code };
+
+        std::process::exit(exit_code);
+    }
+
+    // What happened to each package of each title, in the same order as update_info.
+    let mut outcomes = Vec::new();
+
+    // Bars only get drawn when stdout is a terminal, so piped output stays clean on its own.
+    let progress = MultiProgress::with_draw_target(ProgressDrawTarget::stdout());
+    if silent_mode {
+        progress.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    let mut download_failed = false;
+    let mut merge_failed = false;
+
+    for update in update_info.iter() {
+        let mut title_outcomes = vec![DownloadOutcome::Skipped; update.packages.len()];
+
         let title = {
             if let Some(title) = update.titles.get(0) {
                 title.clone()
@@ -104,7 +349,12 @@ pub fn start_app(args: Args) {
             println!("[{}] {} - {} - {} update(s) ({})", update.platform_variant, update.title_id, &title, update.packages.len(), total_size);
 
             for (i, pkg) in update.packages.iter().enumerate() {
-                println!("  {i}. {} ({})", pkg.id(), ByteSize::b(pkg.size));
+                if version_filter.allows(&update.title_id, pkg) {
+                    println!("  {i}. {} ({})", pkg.id(), ByteSize::b(pkg.size));
+                }
+                else {
+                    println!("  {i}. {} ({}) [skipped by version filter]", pkg.id(), ByteSize::b(pkg.size));
+                }
             }
         }
 
@@ -135,13 +385,13 @@ pub fn start_app(args: Args) {
                 let mut updates = String::new();
 
                 if updates_to_fetch.is_empty() {
-                    for (i, pkg) in update.packages.iter().enumerate() {
-                        updates.push_str(&pkg.id());
-    
-                        if i < update.packages.len() - 1 {
-                            updates.push_str(", ");
-                        }
-                    }
+                    let allowed_updates = update.packages.iter()
+                        .filter(| pkg | version_filter.allows(&update.title_id, pkg))
+                        .map(| pkg | pkg.id())
+                        .collect::<Vec<String>>()
+                    ;
+
+                    updates.push_str(&allowed_updates.join(", "));
                 }
                 else {
                     for (i, update_idx) in updates_to_fetch.iter().enumerate() {
@@ -161,101 +411,716 @@ pub fn start_app(args: Args) {
             crossterm::execute!(std::io::stdout(), terminal::Clear(terminal::ClearType::All), cursor::MoveTo(0, 0)).unwrap();
             println!("{} {} - Downloading update(s): {}", update.title_id, title, updates);
         }
+
+        // Parts of a split update share their patch notes, which only need to be saved once.
+        let mut changeinfo_saved: Vec<String> = Vec::new();
         
         for (idx, pkg) in update.packages.iter().enumerate() {
             if !updates_to_fetch.is_empty() && !updates_to_fetch.contains(&idx) {
                 continue;
             }
 
+            // Updates picked explicitly by the user are always downloaded.
+            if updates_to_fetch.is_empty() && !version_filter.allows(&update.title_id, pkg) {
+                info!("Skipping update {} for {}, excluded by the version filter", pkg.id(), update.title_id);
+                continue;
+            }
+
+            // Passed on to the download, so it doesn't hash the file again.
+            let mut existing_status = None;
+
+            if resume {
+                match runtime.block_on(pre_check_package(pkg, &destination_path, &update.title_id, &title, &download_config)) {
+                    Ok((path, PkgFileStatus::Verified)) => {
+                        info!("Skipping update {} for {}, {:?} is already complete", pkg.id(), update.title_id, path);
+                        title_outcomes[idx] = DownloadOutcome::AlreadyComplete;
+
+                        if !silent_mode {
+                            println!("        {} - {title} | Skipped (already complete)", pkg.id());
+                        }
+
+                        continue;
+                    }
+                    // The download picks partial files up from where they stopped on its own.
+                    Ok((path, status @ PkgFileStatus::Partial { size })) => {
+                        info!("Resuming update {} for {} from byte {size} of {:?}", pkg.id(), update.title_id, path);
+                        existing_status = Some(status);
+                    }
+                    Ok((path, status @ PkgFileStatus::Mismatched)) => {
+                        warn!("{:?} failed verification, downloading update {} for {} again", path, pkg.id(), update.title_id);
+                        existing_status = Some(status);
+                    }
+                    Ok((_, PkgFileStatus::Missing)) => info!("No file for update {} for {} yet, downloading it", pkg.id(), update.title_id),
+                    Err(e) => warn!("Failed to check the existing file for update {} for {}, downloading it anyway: {e}", pkg.id(), update.title_id)
+                }
+            }
+
             let (tx, mut rx) = tokio::sync::mpsc::channel(10);
             let serial = update.title_id.clone();
             let download_path = destination_path.clone();
 
             let dpkg = pkg.clone();
             let dtitle = title.clone();
+            let dconfig = DownloadConfig { existing_status, ..download_config.clone() };
+
+            if downloads_started > 0 && !client_config.request_delay.is_zero() {
+                std::thread::sleep(crate::utils::jittered(client_config.request_delay));
+            }
+
+            downloads_started += 1;
 
             let promise = Promise::spawn_async(
                 async move {
-                    dpkg.start_download(tx, download_path, serial, dtitle).await
+                    dpkg.start_download(tx, download_path, serial, dtitle, dconfig).await
                 }
             );
 
-            let mut stdout = std::io::stdout();
-            let mut downloaded = 0;
-
-            crossterm::execute!(stdout, cursor::SavePosition).unwrap();
-
-            loop {
-                match promise.ready() {
-                    Some(result) => {
-                        if let Err(e) = result {
-                            match e {
-                                DownloadError::HashMismatch(short_on_data) => {
-                                    error!("Download of {} {} failed: hash mismatch. (short on data: {})", update.title_id, pkg.id(), short_on_data);
-                                    println!("Error downloading update: hash mismatch on downloaded file.");
-
-                                    if *short_on_data {
-                                        println!("The downloaded file is smaller than expected. Please try again later, as Sony's servers can sometimes be unreliable");   
-                                    }
-                                }
-                                DownloadError::Tokio(e) => {
-                                    error!("Download of {} {} failed: {e}", update.title_id, pkg.id());
-                                    println!("Error downloading update: {e}.")
-                                }
-                                DownloadError::Reqwest(e) => {
-                                    error!("Download of {} {} failed: {e}", update.title_id, pkg.id());
-                                    println!("Error downloading update: {e}.")
-                                }
-                            }
+            let bar = progress.add(ProgressBar::new(pkg.size));
+            bar.set_style(download_bar_style());
+            bar.set_prefix(format!("{} {} - {title}", update.title_id, pkg.id()));
+            bar.set_message(format_speed(0, None));
+
+            let mut failed = false;
+            // Set while the bar shows the verification spinner, which a failed check or a retry has to switch back from.
+            let mut verifying = false;
+
+            // The sender is dropped once the download is over, so this waits on its statuses instead of polling.
+            while let Some(status) = runtime.block_on(rx.recv()) {
+                match status {
+                    DownloadStatus::Progress(bytes) => {
+                        if verifying {
+                            bar.disable_steady_tick();
+                            bar.set_style(download_bar_style());
+                            verifying = false;
                         }
 
-                        break;
+                        bar.inc(bytes);
+                    }
+                    DownloadStatus::Speed { bytes_per_sec, eta_secs } => {
+                        bar.set_message(format_speed(bytes_per_sec, eta_secs));
                     }
-                    None => {
-                        if let Ok(status) = rx.try_recv() {
-                            match status {
-                                DownloadStatus::Progress(bytes) => {
-                                    downloaded += bytes;
-
-                                    if !silent_mode {
-                                        crossterm::execute!(stdout, cursor::RestorePosition, terminal::Clear(terminal::ClearType::CurrentLine), cursor::SavePosition).unwrap();
-                                        print!("        {} - {title} | {} / {}", pkg.id(), ByteSize::b(downloaded), ByteSize::b(pkg.size));
-                                        stdout.flush().unwrap();
-                                    }
-                                }
-                                DownloadStatus::Verifying => {
-                                    if !silent_mode {
-                                        crossterm::execute!(stdout, cursor::RestorePosition, terminal::Clear(terminal::ClearType::CurrentLine), cursor::SavePosition).unwrap();
-                                        print!("        {} - {title} | Verifying checksum... ", pkg.id());
-                                        stdout.flush().unwrap();
-                                    }
-                                    
-                                }
-                                DownloadStatus::DownloadSuccess => {
-                                    if !silent_mode {
-                                        crossterm::execute!(stdout, cursor::RestorePosition, terminal::Clear(terminal::ClearType::CurrentLine), cursor::SavePosition).unwrap();
-                                        println!("        {} - {title} | Download completed successfully. ", pkg.id());
-                                        stdout.flush().unwrap();
-                                    }
-                                }
-                                DownloadStatus::DownloadFailure => {
-                                    if !silent_mode {
-                                        crossterm::execute!(stdout, cursor::RestorePosition, terminal::Clear(terminal::ClearType::CurrentLine), cursor::SavePosition).unwrap();
-                                        println!("        {} - {title} | Download failed. ", pkg.id());
-                                        stdout.flush().unwrap();
-                                    }
-                                }
-                            }
+                    DownloadStatus::Resuming(offset) => {
+                        if verifying {
+                            bar.disable_steady_tick();
+                            bar.set_style(download_bar_style());
+                            verifying = false;
+                        }
+
+                        bar.set_position(offset);
+
+                        if offset > 0 {
+                            bar.set_message(format!("Resuming from {}...", ByteSize::b(offset)));
+                        }
+                    }
+                    DownloadStatus::Retrying { attempt, max, delay } => {
+                        // The failed attempt's bytes are already counted, the new one reports where it picks up from.
+                        bar.set_message(format!("Transfer failed, retrying in {}s (attempt {attempt}/{max})...", delay.as_secs()));
+                    }
+                    DownloadStatus::Verifying => {
+                        bar.set_style(verifying_bar_style());
+                        bar.set_message("Verifying checksum...");
+                        bar.enable_steady_tick(Duration::from_millis(100));
+                        verifying = true;
+                    }
+                    // Reported once the download's promise resolves.
+                    DownloadStatus::DownloadSuccess | DownloadStatus::DownloadFailure => {}
+                }
+            }
+
+            let result = promise.block_and_take();
+
+            // The bar is swapped for a plain line, so the errors printed below don't get drawn over.
+            bar.finish_and_clear();
+            progress.remove(&bar);
+
+            let outcome = if result.is_ok() { "Download completed successfully." } else { "Download failed." };
+            let outcome_line = format!("        {} | {outcome}", bar.prefix());
+
+            // Nothing gets drawn when stdout isn't a terminal, but the outcome should still show up in piped output.
+            if !progress.is_hidden() {
+                let _ = progress.println(outcome_line);
+            }
+            else if !silent_mode {
+                println!("{outcome_line}");
+            }
+
+            title_outcomes[idx] = if result.is_ok() { DownloadOutcome::Downloaded } else { DownloadOutcome::Failed };
+
+            if result.is_ok() && save_changeinfo && !changeinfo_saved.contains(&pkg.version) {
+                runtime.block_on(pkg.save_changeinfo(&client, destination_path.clone(), &update.title_id, &title, download_config.strict_file_names));
+                changeinfo_saved.push(pkg.version.clone());
+            }
+
+            if let Err(e) = &result {
+                failed = true;
+                download_failed = true;
+
+                match e {
+                    DownloadError::HashMismatch { short_on_data, .. } => {
+                        error!("Download of {} {} failed: {e}", update.title_id, pkg.id());
+                        print_error(structured_output, &format!("Error downloading update: {e}."));
+
+                        if *short_on_data {
+                            print_error(structured_output, "The downloaded file is smaller than expected. Please try again later, as Sony's servers can sometimes be unreliable");
                         }
                     }
+                    DownloadError::Tokio(e) => {
+                        error!("Download of {} {} failed: {e}", update.title_id, pkg.id());
+                        print_error(structured_output, &format!("Error downloading update: {e}."))
+                    }
+                    DownloadError::Reqwest(e) => {
+                        error!("Download of {} {} failed: {e}", update.title_id, pkg.id());
+                        print_error(structured_output, &format!("Error downloading update: {e}."))
+                    }
+                    DownloadError::IoWithPath { .. } => {
+                        error!("Download of {} {} failed: {e}", update.title_id, pkg.id());
+                        print_error(structured_output, &format!("Error downloading update: {e}."))
+                    }
+                    DownloadError::TimedOut => {
+                        error!("Download of {} {} timed out", update.title_id, pkg.id());
+                        print_error(structured_output, "Error downloading update: the request timed out, try again later.")
+                    }
+                    DownloadError::Cancelled => {
+                        error!("Download of {} {} was cancelled", update.title_id, pkg.id());
+                        print_error(structured_output, "Download was cancelled.")
+                    }
+                    DownloadError::InsufficientDiskSpace { required, available } => {
+                        error!("Download of {} {} failed: not enough disk space", update.title_id, pkg.id());
+                        print_error(structured_output, &format!("Error downloading update: not enough disk space ({} required, {} available).", ByteSize::b(*required), ByteSize::b(*available)))
+                    }
                 }
             }
+
+            if failed && stop_on_first_error {
+                warn!("Download of {} {} failed, skipping the remaining updates for the title", update.title_id, pkg.id());
+                print_error(structured_output, &format!("Skipping the remaining updates for {} after a failed download.", update.title_id));
+                break;
+            }
         }
 
-        std::thread::sleep(std::time::Duration::from_secs(3));
+        if update.packages.iter().any(| pkg | pkg.part_number.is_some()) {
+            let all_parts_ready = title_outcomes.iter().all(| o | matches!(o, DownloadOutcome::Downloaded | DownloadOutcome::AlreadyComplete));
+
+            if !all_parts_ready {
+                info!("Not merging {}, not all of its parts were downloaded", update.title_id);
+            }
+            else if merge || (!silent_mode && confirm_merge(update)) {
+                if let Err(e) = merge_title_parts(&runtime, update, &destination_path, download_config.strict_file_names, silent_mode) {
+                    merge_failed = true;
+
+                    error!("Could not merge files for {}, reason: {:?}", update.title_id, e);
+                    print_error(structured_output, &format!("Failed to merge the parts of {}: {e}.", update.title_id));
+                }
+            }
+            else {
+                info!("Not merging {}, merging wasn't requested", update.title_id);
+            }
+        }
+
+        if !pause_between.is_zero() {
+            std::thread::sleep(pause_between);
+        }
         
         if !silent_mode {
             crossterm::execute!(std::io::stdout(), terminal::Clear(terminal::ClearType::All), cursor::MoveTo(0, 0)).unwrap();
         }
+
+        outcomes.push(title_outcomes);
+    }
+
+    if structured_output {
+        let reports = update_info.iter()
+            .zip(outcomes)
+            .map(| (update, title_outcomes) | {
+                TitleReport {
+                    title_id: &update.title_id,
+                    title: update.title(),
+                    platform_variant: update.platform_variant,
+                    packages: update.packages.iter()
+                        .zip(title_outcomes)
+                        .map(| (package, status) | PackageReport { package, status })
+                        .collect()
+                }
+            })
+            .collect::<Vec<TitleReport>>()
+        ;
+
+        if let Err(e) = write_report(output_format, &reports) {
+            error!("Failed to write the {:?} report: {e}", output_format);
+            eprintln!("Failed to write the report: {e}.");
+        }
+    }
+
+    // Whatever runs this should know something's missing, even if the output was silenced.
+    // In the case of merges, the parts are still there to merge by hand, but the pkg isn't usable yet.
+    let exit_code = if merge_failed {
+        EXIT_MERGE_FAILED
+    }
+    else if download_failed {
+        EXIT_DOWNLOAD_FAILED
+    }
+    else if search_failed {
+        EXIT_SEARCH_FAILED
+    }
+    else {
+        0
+    };
+
+    if exit_code != 0 {
+        info!("Exiting with code {exit_code}");
+        std::process::exit(exit_code);
+    }
+}
+
+fn download_bar_style() -> ProgressStyle {
+    ProgressStyle::with_template("        {prefix} [{bar:30}] {bytes} / {total_bytes} | {msg}")
+        .unwrap()
+        .progress_chars("=> ")
+}
+
+fn verifying_bar_style() -> ProgressStyle {
+    ProgressStyle::with_template("        {prefix} {spinner} {msg}").unwrap()
+}
+
+fn confirm_merge(update: &UpdateInfo) -> bool {
+    println!("\n{} was downloaded in {} parts. Merge them into a single pkg? [y/N]", update.title_id, update.packages.len());
+
+    // Not being able to read an answer (eg. stdin was closed) counts as a no.
+    let mut response = String::new();
+    if let Err(e) = std::io::stdin().read_line(&mut response) {
+        warn!("Failed to read the answer to the merge prompt: {e}");
+        return false;
+    }
+
+    response.trim().eq_ignore_ascii_case("y")
+}
+
+// Searches for the updates of each serial, printing an error for the ones that fail.
+// Also returns whether any of them failed. Serials without updates don't count, there's nothing wrong with them.
+fn search_titles(client: &ReqwestClient, titles: Vec<String>, silent_mode: bool, structured_output: bool) -> (Vec<UpdateInfo>, bool) {
+    let mut info = Vec::new();
+    let mut any_failed = false;
+
+    let promises = titles
+        .into_iter()
+        .map(| t | {
+            let client = client.clone();
+            let serial = t.to_string();
+
+            let promise = Promise::spawn_async(async move {
+                let mut attempt = 0;
+
+                loop {
+                    match UpdateInfo::get_info_with(&client, serial.clone()).await {
+                        Err(e) if e.is_retryable() && attempt < SEARCH_RETRIES => {
+                            attempt += 1;
+                            warn!("Search for {serial} failed ({:?}), retrying ({attempt}/{SEARCH_RETRIES})", e);
+
+                            tokio::time::sleep(Duration::from_secs(2)).await;
+                        }
+                        result => break result
+                    }
+                }
+            });
+
+            (t.to_string(), promise)
+        })
+        .collect::<Vec<(String, Promise<Result<UpdateInfo, UpdateError>>)>>()
+    ;
+
+    if !silent_mode {
+        println!("Searching for updates...\n");
+    }
+
+    for (id, promise) in promises {
+        info!("Checking in on search promises");
+
+        match promise.block_and_take() {
+            Ok(i) => {
+                info!("Successfully search for updates for {id}");
+                info.push(i);
+            }
+            Err(e) => {
+                if !matches!(e, UpdateError::NoUpdatesAvailable) {
+                    any_failed = true;
+                }
+
+                match e {
+                    UpdateError::UnhandledErrorResponse(e) => {
+                        error!("Unexpected error received in response from PSN: {e}");
+                        print_error(structured_output, &format!("{id}: PSN returned an unexpected error: {e}."));
+                    }
+                    UpdateError::InvalidSerial => {
+                        error!("Invalid serial for updates query {id}");
+                        print_error(structured_output, &format!("{id}: The provided serial didn't give any results, double-check your input."));
+                    }
+                    UpdateError::NoUpdatesAvailable => {
+                        warn!("No updates available for serial {id}");
+                        print_error(structured_output, &format!("{id}: The provided serial doesn't have any available updates."));
+                    }
+                    UpdateError::Reqwest(e) => {
+                        error!("reqwest error on updates query: {e}");
+                        print_error(structured_output, &format!("{id}: There was an error on the request: {e}."));
+                    }
+                    UpdateError::XmlParsing(e) => {
+                        error!("Failed to deserialize response for {id}: {e}");
+                        print_error(structured_output, &format!("{id}: Error parsing response from PSN, try again later ({e})."));
+                    }
+                    UpdateError::ManifestParsing(e) => {
+                        error!("Failed to deserialize manifest response for {id}: {e}");
+                        print_error(structured_output, &format!("{id}: Error parsing manifest response from PSN, try again later ({e})."));
+                    }
+                    UpdateError::ManifestNotJson(response) => {
+                        error!("Manifest response for {id} wasn't JSON: {response}");
+                        print_error(structured_output, &format!("{id}: PSN's manifest server returned an error page instead of a manifest, try again later ({response})."));
+                    }
+                    UpdateError::TimedOut => {
+                        error!("Updates query for {id} timed out");
+                        print_error(structured_output, &format!("{id}: The request timed out, Sony's servers might be having issues. Try again later."));
+                    }
+                    UpdateError::Cancelled => {
+                        warn!("Search for {id} was cancelled");
+                        print_error(structured_output, &format!("{id}: The search was cancelled."));
+                    }
+                }
+            }
+        }
     }
+
+    (info, any_failed)
+}
+
+fn merge_title_parts(runtime: &Runtime, update: &UpdateInfo, destination_path: &Path, strict_names: bool, silent: bool) -> Result<(), MergeError> {
+    info!("Merging the parts of {}", update.title_id);
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+    let download_path = destination_path.to_path_buf();
+    let update = update.clone();
+    let part_count = update.packages.len();
+
+    let promise = Promise::spawn_async(async move {
+        update.merge_parts(tx, &download_path, strict_names, DEFAULT_MERGED_NAME_TEMPLATE, None, false).await
+    });
+
+    let mut stdout = std::io::stdout();
+
+    if !silent {
+        crossterm::execute!(stdout, cursor::SavePosition).unwrap();
+    }
+
+    // The sender is dropped once the merge is over, whether it worked or not.
+    while let Some(status) = runtime.block_on(rx.recv()) {
+        if silent {
+            continue;
+        }
+
+        match status {
+            MergeStatus::PartProgress(merged) => {
+                crossterm::execute!(stdout, cursor::RestorePosition, terminal::Clear(terminal::ClearType::CurrentLine), cursor::SavePosition).unwrap();
+                print!("Merging parts... {merged}/{part_count}");
+                stdout.flush().unwrap();
+            }
+            MergeStatus::Verifying => {
+                crossterm::execute!(stdout, cursor::RestorePosition, terminal::Clear(terminal::ClearType::CurrentLine), cursor::SavePosition).unwrap();
+                print!("Verifying merged file...");
+                stdout.flush().unwrap();
+            }
+            MergeStatus::ByteProgress { .. } | MergeStatus::CleaningUp | MergeStatus::MergeSuccess | MergeStatus::MergeFailure => {}
+        }
+    }
+
+    let result = promise.block_and_take();
+
+    if !silent {
+        crossterm::execute!(stdout, cursor::RestorePosition, terminal::Clear(terminal::ClearType::CurrentLine)).unwrap();
+
+        if result.is_ok() {
+            println!("Parts merged successfully.");
+        }
+    }
+
+    result
+}
+
+// Works out where each package of the update would be saved, without writing anything.
+// Existing files are only read, to tell whether they'd be downloaded again.
+pub async fn plan_download(update: &UpdateInfo, destination_path: &Path, config: &DownloadConfig) -> Vec<DownloadPlan> {
+    let destination_path = destination_path.to_path_buf();
+    let title = update.title();
+    let mut plans = Vec::new();
+
+    for pkg in update.packages.iter() {
+        let file_name = pkg.file_name().unwrap_or_else(|| String::from("update.pkg"));
+        let path = crate::utils::resolve_pkg_path(&destination_path, &update.title_id, &title, &file_name, config.strict_file_names);
+
+        let verified = if config.skip_verify_existing {
+            matches!(tokio::fs::metadata(&path).await, Ok(metadata) if metadata.len() == pkg.size)
+        }
+        else {
+            match pkg.check_file(&path, config.sha1_backend).await {
+                Ok(status) => status == PkgFileStatus::Verified,
+                Err(e) => {
+                    warn!("Failed to check {:?} for the dry run: {e}", path);
+                    false
+                }
+            }
+        };
+
+        plans.push(DownloadPlan { path, size: pkg.size, url: pkg.url.clone(), verified });
+    }
+
+    plans
+}
+
+fn print_dry_run(plans: &[(UpdateInfo, Vec<DownloadPlan>)], format: OutputFormat, silent: bool) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+
+    match format {
+        OutputFormat::Json => {
+            let plans = plans.iter().flat_map(| (_, plan) | plan.iter()).collect::<Vec<&DownloadPlan>>();
+
+            serde_json::to_writer(&mut stdout, &plans)?;
+            writeln!(stdout)?;
+        }
+        OutputFormat::Csv => {
+            writeln!(stdout, "path,size,url,verified")?;
+
+            for plan in plans.iter().flat_map(| (_, plan) | plan.iter()) {
+                writeln!(stdout, "{},{},{},{}", csv_field(&plan.path.to_string_lossy()), plan.size, csv_field(&plan.url), plan.verified)?;
+            }
+        }
+        // One line per file and tab separated, easy to pick apart with cut or awk.
+        OutputFormat::Text if silent => {
+            for plan in plans.iter().flat_map(| (_, plan) | plan.iter()) {
+                writeln!(stdout, "{}\t{}\t{}\t{}", plan.path.display(), plan.size, plan.url, plan.verified)?;
+            }
+        }
+        OutputFormat::Text => {
+            let (mut total, mut to_download) = (0, 0);
+
+            for (update, plan) in plans {
+                let folder = plan.first().and_then(| p | p.path.parent()).map(| p | p.display().to_string()).unwrap_or_default();
+                writeln!(stdout, "{}", format!("[{}] {} - {}", update.platform_variant, update.title_id, update.title()).bold())?;
+                writeln!(stdout, "  Saved to {folder}")?;
+
+                let name_width = plan.iter()
+                    .filter_map(| p | p.path.file_name())
+                    .map(| n | n.to_string_lossy().len())
+                    .max()
+                    .unwrap_or(0)
+                    .max(4)
+                ;
+
+                writeln!(stdout, "  {}", format!("{:<name_width$}  {:>10}  {:<12}  URL", "File", "Size", "Status").underlined())?;
+
+                for p in plan {
+                    let name = p.path.file_name().map(| n | n.to_string_lossy().to_string()).unwrap_or_default();
+                    // Padded before styling, the escape codes would throw the width off otherwise.
+                    let status = if p.verified { format!("{:<12}", "already done").green() } else { format!("{:<12}", "to download").yellow() };
+
+                    writeln!(stdout, "  {:<name_width$}  {:>10}  {}  {}", name, ByteSize::b(p.size).to_string(), status, p.url)?;
+
+                    total += p.size;
+                    if !p.verified {
+                        to_download += p.size;
+                    }
+                }
+
+                writeln!(stdout)?;
+            }
+
+            writeln!(stdout, "Dry run, nothing was downloaded. {} to download ({} in total).", ByteSize::b(to_download), ByteSize::b(total))?;
+        }
+    }
+
+    stdout.flush()
+}
+
+// In text mode, errors are shown along with everything else. Otherwise stdout is reserved for the report.
+fn print_error(structured_output: bool, message: &str) {
+    if structured_output {
+        eprintln!("{message}");
+    }
+    else {
+        println!("{message}");
+    }
+}
+
+fn write_report(format: OutputFormat, reports: &[TitleReport]) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+
+    match format {
+        OutputFormat::Text => {}
+        OutputFormat::Json => {
+            serde_json::to_writer(&mut stdout, reports)?;
+            writeln!(stdout)?;
+        }
+        OutputFormat::Csv => {
+            writeln!(stdout, "title_id,version,size,sha1sum,url,status")?;
+
+            for report in reports {
+                for pkg in report.packages.iter() {
+                    let status = match pkg.status {
+                        DownloadOutcome::Downloaded => "downloaded",
+                        DownloadOutcome::AlreadyComplete => "already_complete",
+                        DownloadOutcome::Failed => "failed",
+                        DownloadOutcome::Skipped => "skipped"
+                    };
+
+                    writeln!(
+                        stdout, "{},{},{},{},{},{status}",
+                        csv_field(report.title_id), csv_field(&pkg.package.version), pkg.package.size, csv_field(&pkg.package.sha1sum), csv_field(&pkg.package.url)
+                    )?;
+                }
+            }
+        }
+    }
+
+    stdout.flush()
+}
+
+// Quotes a value when it has characters that would break the row apart.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    }
+    else {
+        value.to_string()
+    }
+}
+
+fn verify_existing_folder(runtime: &Runtime, update_info: &[UpdateInfo], folder: &Path, backend: Sha1Backend) {
+    for update in update_info {
+        println!("{} - {} | Verifying files in {}", update.title_id, update.title(), folder.display());
+
+        match runtime.block_on(update.verify_folder(folder, backend)) {
+            Ok(result) => {
+                for pkg_id in result.verified.iter() {
+                    println!("  {pkg_id}: OK");
+                }
+
+                for pkg_id in result.mismatched.iter() {
+                    println!("  {pkg_id}: hash mismatch");
+                }
+
+                for pkg_id in result.missing.iter() {
+                    println!("  {pkg_id}: missing");
+                }
+
+                for path in result.unmatched_files.iter() {
+                    println!("  {}: doesn't belong to any of the updates", path.display());
+                }
+            }
+            Err(e) => {
+                error!("Failed to verify {:?} for {}: {e}", folder, update.title_id);
+                println!("  Failed to verify the folder: {e}.");
+            }
+        }
+    }
+}
+
+// Hashes the files that downloads of the given updates would have created, in the folders they'd be saved to.
+// Returns the exit code: 0 if every file is fine, EXIT_VERIFY_MISMATCH if any of them is wrong or incomplete,
+// and EXIT_VERIFY_MISSING if any is missing.
+fn verify_downloaded(runtime: &Runtime, update_info: &[UpdateInfo], destination_path: &Path, config: &DownloadConfig, silent: bool) -> i32 {
+    let destination_path = destination_path.to_path_buf();
+    let (mut any_mismatched, mut any_missing) = (false, false);
+
+    for update in update_info {
+        let title = update.title();
+
+        if !silent {
+            println!("{} - {title}", update.title_id);
+        }
+
+        for pkg in update.packages.iter() {
+            let path = pkg.file_path(&destination_path, &update.title_id, &title, config.strict_file_names);
+            let file_name = pkg.file_name().unwrap_or_else(|| String::from("update.pkg"));
+
+            let result = match runtime.block_on(pkg.check_file(&path, config.sha1_backend)) {
+                Ok(PkgFileStatus::Verified) => String::from("OK"),
+                Ok(PkgFileStatus::Mismatched) => {
+                    any_mismatched = true;
+                    String::from("hash mismatch")
+                }
+                Ok(PkgFileStatus::Partial { size }) => {
+                    any_mismatched = true;
+                    format!("incomplete ({} / {})", ByteSize::b(size), ByteSize::b(pkg.size))
+                }
+                Ok(PkgFileStatus::Missing) => {
+                    any_missing = true;
+                    String::from("missing")
+                }
+                Err(e) => {
+                    error!("Failed to verify {:?}: {e}", path);
+                    any_mismatched = true;
+                    format!("failed to check the file: {e}")
+                }
+            };
+
+            info!("Verified {} {} at {:?}: {result}", update.title_id, pkg.id(), path);
+
+            if !silent {
+                println!("  {file_name} ({}): {result}", pkg.sha1sum);
+            }
+        }
+    }
+
+    if any_missing {
+        EXIT_VERIFY_MISSING
+    }
+    else if any_mismatched {
+        EXIT_VERIFY_MISMATCH
+    }
+    else {
+        0
+    }
+}
+
+fn report_plan_status(runtime: &Runtime, plan_path: &Path, destination_path: &Path, backend: Sha1Backend, strict_names: bool) {
+    let plan = match load_plan(plan_path) {
+        Ok(plan) => plan,
+        Err(e) => {
+            error!("Failed to load plan from {:?}: {e}", plan_path);
+            println!("Failed to load the plan from {}: {e}.", plan_path.display());
+            return;
+        }
+    };
+
+    let destination_path = destination_path.to_path_buf();
+    let (mut complete, mut total) = (0, 0);
+
+    for update in plan.iter() {
+        println!("{} - {}", update.title_id, update.title());
+
+        let title_path = crate::utils::create_new_pkg_path(&destination_path, &update.title_id, &update.title(), strict_names);
+
+        for pkg in update.packages.iter() {
+            total += 1;
+
+            let file_name = match pkg.file_name() {
+                Some(name) => name,
+                None => {
+                    println!("  {}: unknown file name", pkg.id());
+                    continue;
+                }
+            };
+
+            match runtime.block_on(pkg.check_file(&title_path.join(file_name), backend)) {
+                Ok(PkgFileStatus::Verified) => {
+                    complete += 1;
+                    println!("  {}: OK", pkg.id());
+                }
+                Ok(PkgFileStatus::Partial { size }) => println!("  {}: partial ({} / {})", pkg.id(), ByteSize::b(size), ByteSize::b(pkg.size)),
+                Ok(PkgFileStatus::Mismatched) => println!("  {}: hash mismatch", pkg.id()),
+                Ok(PkgFileStatus::Missing) => println!("  {}: missing", pkg.id()),
+                Err(e) => {
+                    error!("Failed to check {} {}: {e}", update.title_id, pkg.id());
+                    println!("  {}: failed to check the file: {e}", pkg.id());
+                }
+            }
+        }
+    }
+
+    println!("\n{complete} of {total} file(s) complete.");
 }
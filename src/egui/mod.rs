@@ -1,5 +1,7 @@
-use std::path::PathBuf;
-use std::time::Duration;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use eframe::egui;
 use egui_notify::{Toast, Toasts, ToastLevel};
@@ -10,10 +12,17 @@ use notify_rust::Notification;
 use serde::{Deserialize, Serialize};
 use copypasta::{ClipboardContext, ClipboardProvider};
 
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, Semaphore};
 use tokio::runtime::Runtime;
 
 use crate::psn::*;
+use crate::utils::{create_new_pkg_path, default_download_path, format_age, format_speed, Sha1Backend};
+use crate::psn::utils::get_regional_variants;
+use crate::psn::client::{validate_proxy_url, ClientConfig, PoolConfig, RateLimiter, ReqwestClient, DEFAULT_CONNECT_TIMEOUT_SECS, DEFAULT_REQUEST_TIMEOUT_SECS};
+
+// The speed shown for a download is averaged over the reports received in the last few seconds.
+const SPEED_SAMPLE_CAPACITY: usize = 10;
+const SPEED_SAMPLE_WINDOW: Duration = Duration::from_secs(5);
 
 pub struct ActiveDownload {
     title_id: String,
@@ -21,65 +30,459 @@ pub struct ActiveDownload {
 
     size: u64,
     progress: u64,
+    // The latest speed reports from the download, with when they arrived.
+    speed_samples: VecDeque<(Instant, u64)>,
+    // Waiting for one of the running downloads to finish. Cleared once the download sends its first status.
+    queued: bool,
     last_received_status: DownloadStatus,
+    // When the next attempt starts, while waiting to retry a failed transfer.
+    retry_deadline: Option<Instant>,
 
     promise: Promise<Result<(), DownloadError>>,
     progress_rx: mpsc::Receiver<DownloadStatus>
 }
 
+impl ActiveDownload {
+    fn status(&self) -> ActiveDownloadStatus {
+        if self.queued {
+            return ActiveDownloadStatus::Queued;
+        }
+
+        match self.last_received_status {
+            DownloadStatus::Progress(_) | DownloadStatus::Speed { .. } | DownloadStatus::Resuming(_) => {
+                let speed_bps = self.speed();
+                let eta_secs = if speed_bps > 0 { Some(self.size.saturating_sub(self.progress) / speed_bps) } else { None };
+
+                ActiveDownloadStatus::Downloading { progress: self.progress as f32 / self.size as f32, speed_bps, eta_secs }
+            }
+            DownloadStatus::Verifying => ActiveDownloadStatus::Verifying,
+            DownloadStatus::Retrying { attempt, max, .. } => {
+                let remaining = self.retry_deadline
+                    .map(| deadline | deadline.saturating_duration_since(Instant::now()))
+                    .unwrap_or_default()
+                ;
+
+                ActiveDownloadStatus::Retrying { seconds: remaining.as_secs_f32().ceil() as u64, attempt, max }
+            }
+            _ => ActiveDownloadStatus::NotStarted
+        }
+    }
+
+    fn record_speed(&mut self, bytes_per_sec: u64) {
+        let now = Instant::now();
+        self.speed_samples.push_back((now, bytes_per_sec));
+
+        while let Some((received_at, _)) = self.speed_samples.front() {
+            if self.speed_samples.len() > SPEED_SAMPLE_CAPACITY || now.duration_since(*received_at) > SPEED_SAMPLE_WINDOW {
+                self.speed_samples.pop_front();
+            }
+            else {
+                break;
+            }
+        }
+    }
+
+    // Rolling average speed in bytes per second, over the recent reports.
+    fn speed(&self) -> u64 {
+        if self.speed_samples.is_empty() {
+            return 0;
+        }
+
+        self.speed_samples.iter().map(| (_, bytes_per_sec) | bytes_per_sec).sum::<u64>() / self.speed_samples.len() as u64
+    }
+}
+
 pub struct ActiveMerge {
     title_id: String,
 
     part_progress: usize,
+    // Bytes copied into the merged files so far, out of total_bytes.
+    bytes_merged: u64,
+    total_bytes: u64,
+    // Size of the parts that haven't been fully copied yet, as (part number, size), in the order they're merged.
+    pending_parts: Vec<(usize, u64)>,
     last_received_status: MergeStatus,
 
     promise: Promise<Result<(), MergeError>>,
     progress_rx: mpsc::Receiver<MergeStatus>
 }
 
+impl ActiveMerge {
+    fn finished_parts_size(&self) -> u64 {
+        self.total_bytes - self.pending_parts.iter().map(| (_, size) | size).sum::<u64>()
+    }
+
+    fn record_status(&mut self, status: &MergeStatus) {
+        match status {
+            MergeStatus::ByteProgress { bytes_written, .. } => {
+                self.bytes_merged = self.finished_parts_size() + bytes_written;
+            }
+            MergeStatus::PartProgress(part) => {
+                self.part_progress = *part;
+
+                // Parts of different versions can share a number, they're merged in package order.
+                if let Some(idx) = self.pending_parts.iter().position(| (number, _) | number == part) {
+                    self.pending_parts.remove(idx);
+                }
+
+                self.bytes_merged = self.finished_parts_size();
+            }
+            _ => {}
+        }
+    }
+
+    fn progress(&self) -> f32 {
+        if self.total_bytes == 0 {
+            return 0.0;
+        }
+
+        self.bytes_merged as f32 / self.total_bytes as f32
+    }
+}
+
+pub struct ActiveSearch {
+    serial: String,
+    // Manifests fetched so far for a PS4 search, as (fetched, total).
+    progress: Option<(usize, usize)>,
+
+    promise: Promise<Result<UpdateInfo, UpdateError>>,
+    progress_rx: mpsc::Receiver<SearchStatus>
+}
+
+// Smallest size the window is restored to, so a bogus stored size can't leave it unusable.
+const MIN_WINDOW_SIZE: [f32; 2] = [320.0, 240.0];
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+struct WindowGeometry {
+    // Position of the window's outer rect, including decorations.
+    position: [f32; 2],
+    // Size of the window's inner rect.
+    size: [f32; 2]
+}
+
+impl WindowGeometry {
+    // The monitor the window was on might not be connected anymore, or its resolution could have changed.
+    // Only the size of the current monitor is known, so the window is clamped to fit in it.
+    fn clamped_to(&self, monitor_size: egui::Vec2) -> WindowGeometry {
+        let width = self.size[0].min(monitor_size.x).max(MIN_WINDOW_SIZE[0]);
+        let height = self.size[1].min(monitor_size.y).max(MIN_WINDOW_SIZE[1]);
+
+        let x = self.position[0].min(monitor_size.x - width).max(0.0);
+        let y = self.position[1].min(monitor_size.y - height).max(0.0);
+
+        WindowGeometry {
+            position: [x, y],
+            size: [width, height]
+        }
+    }
+}
+
+// Big multipart PS4 updates would otherwise start every part at once and saturate the connection.
+const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 3;
+const MAX_CONCURRENT_DOWNLOADS_LIMIT: usize = 8;
+// Starting value when the download speed limit gets enabled.
+const DEFAULT_MAX_DOWNLOAD_RATE_KB_PER_SEC: u64 = 5000;
+
+// Settings file that's picked up on startup if no other path was provided with --config-toml.
+const DEFAULT_CONFIG_TOML: &str = "rusty-psn.toml";
+
 #[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
 struct AppSettings {
     pkg_download_path: PathBuf,
     show_toasts: bool,
     show_notifications: bool,
+    // Free space (in GB) that downloads have to leave on the disk.
+    min_free_space_gb: u64,
+    // Hash existing files before considering them complete. When disabled, matching the expected size is enough.
+    verify_existing_files: bool,
+    // Save the patch notes of downloaded updates next to their pkgs.
+    save_changeinfo: bool,
+    // Keep downloads that fail verification in a failed/ folder, to see what Sony actually sent.
+    quarantine_failed_files: bool,
+    // Cancel the rest of a title's downloads once one of them fails.
+    stop_on_first_error: bool,
+    // Name for merged PS4 pkgs, see DEFAULT_MERGED_NAME_TEMPLATE for the placeholders.
+    merged_name_template: String,
+    // Where merged pkgs are put together before being moved next to the parts. The title's folder if unset.
+    scratch_path: Option<PathBuf>,
+    // Remove the parts of split PS4 updates once they've been merged and the result checked.
+    #[serde(alias = "delete_parts_after_merge")]
+    auto_delete_parts_after_merge: bool,
+    // Start merging a split PS4 update as soon as the last of its parts is downloaded.
+    #[serde(alias = "auto_merge")]
+    auto_merge_after_download: bool,
+    sha1_backend: Sha1Backend,
+    // Version patterns to only download, or to always skip, separated by commas. See VersionFilter.
+    version_allowlist: String,
+    version_blocklist: String,
+    // Cookies sent to PSN's metadata servers, in "name=value; name2=value2" form.
+    psn_cookies: String,
+    // Time allowed to establish a connection. Should be short, a dead server won't answer no matter how long we wait.
+    connect_timeout_secs: u64,
+    // Time allowed for a search request to complete, or for a download to go without receiving any data.
+    request_timeout_secs: u64,
+    // Connection reuse tuning, see PoolConfig. Only set through the TOML settings file (see merge_toml), reqwest's defaults are used otherwise.
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout_secs: Option<u64>,
+    // Pause between requests to Sony's servers, and between starting the downloads of a batch.
+    request_delay_ms: u64,
+    // Proxy for every request, eg. socks5://host:port. The HTTPS_PROXY and ALL_PROXY environment variables are used when unset.
+    proxy_url: Option<String>,
+    // How many more times a download that drops or fails verification is attempted.
+    max_retries: u32,
+    // Combined speed cap for every running download, in KB/s (thousands of bytes, not kilobits).
+    #[serde(alias = "max_download_rate_kbps")]
+    max_download_rate_kb_per_sec: Option<u64>,
+    // Whether each section of the settings window is expanded. Missing sections are open.
+    settings_section_open: HashMap<String, bool>,
+    // Shows the raw parsed values of each package, for diagnosing download and merge issues.
+    developer_mode: bool,
+    // How many downloads can run at the same time. The rest wait in the queue.
+    max_concurrent_downloads: usize,
+    // Keep folder names valid on Windows even on other OSes, for downloads to shared drives.
+    strict_file_names: bool,
+    // Where the window was when the app was last closed.
+    window_geometry: Option<WindowGeometry>,
 }
 
 impl Default for AppSettings {
     fn default() -> AppSettings {
         AppSettings {
-            pkg_download_path: PathBuf::from("pkgs/"),
+            pkg_download_path: default_download_path(),
             show_toasts: true,
-            show_notifications: false
+            show_notifications: false,
+            min_free_space_gb: 0,
+            verify_existing_files: true,
+            save_changeinfo: false,
+            quarantine_failed_files: false,
+            stop_on_first_error: false,
+            merged_name_template: String::from(DEFAULT_MERGED_NAME_TEMPLATE),
+            scratch_path: None,
+            auto_delete_parts_after_merge: false,
+            auto_merge_after_download: false,
+            sha1_backend: Sha1Backend::default(),
+            version_allowlist: String::new(),
+            version_blocklist: String::new(),
+            psn_cookies: String::new(),
+            connect_timeout_secs: DEFAULT_CONNECT_TIMEOUT_SECS,
+            request_timeout_secs: DEFAULT_REQUEST_TIMEOUT_SECS,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout_secs: None,
+            request_delay_ms: 0,
+            proxy_url: None,
+            max_retries: DEFAULT_DOWNLOAD_RETRIES,
+            max_download_rate_kb_per_sec: None,
+            settings_section_open: HashMap::new(),
+            developer_mode: false,
+            max_concurrent_downloads: DEFAULT_MAX_CONCURRENT_DOWNLOADS,
+            strict_file_names: false,
+            window_geometry: None
+        }
+    }
+}
+
+impl AppSettings {
+    fn download_config(&self) -> DownloadConfig {
+        DownloadConfig {
+            min_free_space: ByteSize::gb(self.min_free_space_gb).as_u64(),
+            skip_verify_existing: !self.verify_existing_files,
+            connect_timeout: Some(Duration::from_secs(self.connect_timeout_secs)),
+            transfer_timeout: Some(Duration::from_secs(self.request_timeout_secs)),
+            sha1_backend: self.sha1_backend,
+            pool: self.pool_config(),
+            proxy: self.proxy_url.clone(),
+            max_retries: self.max_retries,
+            quarantine_failed: self.quarantine_failed_files,
+            strict_file_names: self.strict_file_names,
+            // Set by the app, so every download shares the same limiter.
+            rate_limit: None,
+            existing_status: None,
+            client: None
+        }
+    }
+
+    fn version_filter(&self) -> VersionFilter {
+        let split_patterns = | patterns: &str | -> Vec<String> {
+            patterns
+                .split(| c: char | c == ',' || c.is_whitespace())
+                .filter(| p | !p.is_empty())
+                .map(| p | p.to_string())
+                .collect()
+        };
+
+        VersionFilter {
+            allowlist: split_patterns(&self.version_allowlist),
+            blocklist: split_patterns(&self.version_blocklist)
+        }
+    }
+
+    fn client_config(&self) -> ClientConfig {
+        ClientConfig {
+            cookies: self.psn_cookies.split(';').map(| c | c.trim().to_string()).filter(| c | !c.is_empty()).collect(),
+            connect_timeout: Some(Duration::from_secs(self.connect_timeout_secs)),
+            request_timeout: Some(Duration::from_secs(self.request_timeout_secs)),
+            pool: self.pool_config(),
+            proxy: self.proxy_url.clone(),
+            request_delay: Duration::from_millis(self.request_delay_ms)
+        }
+    }
+
+    fn pool_config(&self) -> PoolConfig {
+        PoolConfig {
+            max_idle_per_host: self.pool_max_idle_per_host,
+            idle_timeout: self.pool_idle_timeout_secs.map(Duration::from_secs)
         }
     }
+
+    // Returns a copy of these settings with the values from a TOML document applied on top of them.
+    // Keys that aren't present in the TOML keep their current value, unknown ones are ignored.
+    fn merge_toml(&self, path: &Path, contents: &str) -> Result<AppSettings, toml::de::Error> {
+        let overrides: toml::Table = toml::from_str(contents)?;
+
+        let mut merged = match toml::Value::try_from(self).map_err(<toml::de::Error as serde::de::Error>::custom)? {
+            toml::Value::Table(table) => table,
+            _ => toml::Table::new()
+        };
+
+        for (key, value) in overrides {
+            let key = RENAMED_SETTINGS_KEYS.iter()
+                .find(| (old, _) | *old == key)
+                .map(| (_, new) | new.to_string())
+                .unwrap_or(key)
+            ;
+
+            if !SETTINGS_KEYS.contains(&key.as_str()) {
+                warn!("Ignoring unknown setting '{key}' in {:?}", path);
+                continue;
+            }
+
+            merged.insert(key, value);
+        }
+
+        toml::Value::Table(merged).try_into()
+    }
+}
+
+// Keys that merge_toml accepts. The serialized settings can't be used for this, TOML leaves out the fields that are None.
+const SETTINGS_KEYS: &[&str] = &[
+    "pkg_download_path", "show_toasts", "show_notifications", "min_free_space_gb", "verify_existing_files", "save_changeinfo",
+    "quarantine_failed_files", "stop_on_first_error", "merged_name_template", "scratch_path", "auto_delete_parts_after_merge",
+    "auto_merge_after_download", "sha1_backend", "version_allowlist", "version_blocklist", "psn_cookies", "connect_timeout_secs",
+    "request_timeout_secs", "pool_max_idle_per_host", "pool_idle_timeout_secs", "request_delay_ms", "proxy_url", "max_retries",
+    "max_download_rate_kb_per_sec", "settings_section_open", "developer_mode", "max_concurrent_downloads", "strict_file_names", "window_geometry"
+];
+
+// Old names of settings that were renamed, and their current ones. The serde aliases don't help here, having both
+// the old key from the file and the new one from the current settings would make deserializing fail.
+const RENAMED_SETTINGS_KEYS: &[(&str, &str)] = &[
+    ("delete_parts_after_merge", "auto_delete_parts_after_merge"), ("max_download_rate_kbps", "max_download_rate_kb_per_sec")
+];
+
+// Stored under its own key so the settings can be restored to defaults without losing it.
+const HISTORY_KEY: &str = "download_history";
+// Search results and when they were fetched, so they don't have to be searched for again after a restart.
+const RESULTS_KEY: &str = "search_results";
+// Oldest entries are dropped past this, to keep the storage file from growing forever.
+const MAX_HISTORY_ENTRIES: usize = 500;
+
+// Finished downloads, kept between runs so their badges are still shown after a restart.
+#[derive(Default, Deserialize, Serialize)]
+struct PersistentHistory {
+    completed_downloads: Vec<(String, String)>,
+    failed_downloads: Vec<(String, String)>
+}
+
+impl PersistentHistory {
+    fn push_completed(&mut self, title_id: String, pkg_id: String) {
+        push_capped(&mut self.completed_downloads, (title_id, pkg_id));
+    }
+
+    fn push_failed(&mut self, title_id: String, pkg_id: String) {
+        push_capped(&mut self.failed_downloads, (title_id, pkg_id));
+    }
+
+    fn clear(&mut self) {
+        self.completed_downloads.clear();
+        self.failed_downloads.clear();
+    }
+}
+
+fn push_capped(list: &mut Vec<(String, String)>, entry: (String, String)) {
+    list.push(entry);
+
+    if list.len() > MAX_HISTORY_ENTRIES {
+        let excess = list.len() - MAX_HISTORY_ENTRIES;
+        list.drain(..excess);
+    }
 }
 
 // Values that shouldn't be persisted from run to run.
 struct VolatileData {
     rt: Runtime,
+    // Limits how many downloads run at once, holding max_concurrent_downloads permits.
+    // Replaced when the setting changes, downloads that already hold a permit of the old one are left to finish.
+    download_semaphore: (usize, Arc<Semaphore>),
     toasts: Toasts,
     
     clipboard: Option<Box<dyn ClipboardProvider>>,
 
     serial_query: String,
     update_results: Vec<UpdateInfo>,
+    // When each title's results were fetched, in seconds since the epoch.
+    results_fetched_at: HashMap<String, u64>,
+    // Only results for this platform are listed when set.
+    platform_filter: Option<utils::PlaformVariant>,
 
     show_settings_window: bool,
+    show_download_queue_window: bool,
     show_mismatch_warning_window: bool,
+    // What was checked (the hash algorithm, or the size for packages without a hash), and the expected
+    // and actual values for the download the mismatch window is about.
+    mismatch_hashes: Option<(String, String, String)>,
 
     settings_dirty: bool,
     modified_settings: AppSettings,
+    config_toml_path: PathBuf,
+
+    // Built from the settings on first use and shared by every search and download, so connections get reused.
+    // Cleared when the settings change.
+    search_client: Option<ReqwestClient>,
+    download_config: Option<DownloadConfig>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    // Title and version of the patch notes that were already saved this session.
+    saved_changeinfo: Vec<(String, String)>,
 
     download_queue: Vec<ActiveDownload>,
-    failed_downloads: Vec<(String, String)>,
-    completed_downloads: Vec<(String, String)>,
 
     merge_queue: Vec<ActiveMerge>,
     failed_merges: Vec<String>,
     completed_merges: Vec<String>,
 
-
-    search_promise: Option<Promise<Result<UpdateInfo, UpdateError>>>
+    // Aggregate progress for the downloads queued since the queue was last empty.
+    // Used to show the overall progress in the window title.
+    window_title: String,
+    batch_download_count: usize,
+    batch_total_size: u64,
+    batch_finished_size: u64,
+
+    // Whether the stored window geometry was applied. The current geometry isn't tracked until then,
+    // so the default window doesn't overwrite the stored one.
+    window_geometry_restored: bool,
+
+    // Downloads waiting on a connectivity check before being queued, as (serial, title, pkg).
+    pending_batch: Option<(Promise<bool>, Vec<(String, String, PackageInfo)>)>,
+
+    // Searches run side by side, up to the same limit as downloads.
+    search_promises: Vec<ActiveSearch>,
+    // Serials waiting for a free search slot, eg. from importing a list.
+    queued_searches: VecDeque<String>,
+    // Serial of the last search that failed with an error worth retrying.
+    retryable_search: Option<String>,
+    // Searches for the regional variants of a serial, paired with the serial they were made for.
+    region_search_promise: Option<Promise<Vec<(String, Result<UpdateInfo, UpdateError>)>>>,
+    // Packages shared by more than one of the results, by SHA-1 hash. See find_duplicate_packages.
+    duplicate_packages: HashMap<String, Vec<(String, String)>>
 }
 
 impl Default for VolatileData {
@@ -89,13 +492,14 @@ impl Default for VolatileData {
                 Ok(clip) => Some(Box::new(clip)),
                 Err(e) => {
                     error!("Failed to init clipboard: {}", e.to_string());
-                    None
+                    primary_selection_clipboard()
                 }
             }
         };
 
         VolatileData {
             rt: Runtime::new().unwrap(),
+            download_semaphore: (DEFAULT_MAX_CONCURRENT_DOWNLOADS, Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENT_DOWNLOADS))),
             toasts: Toasts::default()
                 .reverse(true)
                 .with_anchor(egui_notify::Anchor::BottomRight),
@@ -104,36 +508,111 @@ impl Default for VolatileData {
 
             serial_query: String::new(),
             update_results: Vec::new(),
+            results_fetched_at: HashMap::new(),
+            platform_filter: None,
 
             show_settings_window: false,
+            show_download_queue_window: false,
             show_mismatch_warning_window: false,
+            mismatch_hashes: None,
 
             settings_dirty: false,
             modified_settings: AppSettings::default(),
+            config_toml_path: PathBuf::from(DEFAULT_CONFIG_TOML),
+
+            search_client: None,
+            download_config: None,
+            rate_limiter: None,
+            saved_changeinfo: Vec::new(),
 
             download_queue: Vec::new(),
-            failed_downloads: Vec::new(),
-            completed_downloads: Vec::new(),
 
             merge_queue: Vec::new(),
             failed_merges: Vec::new(),
             completed_merges: Vec::new(),
 
-            search_promise: None
+            window_title: String::from("rusty-psn"),
+            batch_download_count: 0,
+            batch_total_size: 0,
+            batch_finished_size: 0,
+
+            window_geometry_restored: false,
+
+            pending_batch: None,
+
+            search_promises: Vec::new(),
+            queued_searches: VecDeque::new(),
+            retryable_search: None,
+            region_search_promise: None,
+            duplicate_packages: HashMap::new()
         }
     }
 }
 
+const CLIPBOARD_UNAVAILABLE_TEXT: &str = "Clipboard unavailable in this environment";
+
+// Some setups (headless, Wayland without XWayland) can't provide the regular clipboard,
+// but the X11 primary selection might still be around.
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "ios", target_os = "emscripten"))))]
+fn primary_selection_clipboard() -> Option<Box<dyn ClipboardProvider>> {
+    use copypasta::x11_clipboard::{Primary, X11ClipboardContext};
+
+    match X11ClipboardContext::<Primary>::new() {
+        Ok(clip) => {
+            info!("Falling back to the X11 primary selection for the clipboard");
+            Some(Box::new(clip))
+        }
+        Err(e) => {
+            error!("Failed to init X11 primary selection clipboard: {}", e.to_string());
+            None
+        }
+    }
+}
+
+#[cfg(not(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "ios", target_os = "emscripten")))))]
+fn primary_selection_clipboard() -> Option<Box<dyn ClipboardProvider>> {
+    None
+}
+
+// Shows a folder in the system's file browser.
+fn open_folder(path: &Path) -> std::io::Result<()> {
+    #[cfg(target_os = "windows")]
+    let program = "explorer";
+    #[cfg(target_os = "macos")]
+    let program = "open";
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let program = "xdg-open";
+
+    // Not waited on, the file browser keeps running on its own.
+    std::process::Command::new(program).arg(path).spawn().map(| _ | ())
+}
+
+fn search_error_message(e: &UpdateError) -> String {
+    match e {
+        UpdateError::InvalidSerial => String::from("The provided serial didn't give any results, double-check your input."),
+        UpdateError::NoUpdatesAvailable => String::from("The provided serial doesn't have any available updates."),
+        UpdateError::ManifestNotJson(response) => format!("Sony's manifest server returned an error page instead of a manifest, try again later ({response})."),
+        UpdateError::TimedOut => String::from("The request timed out, Sony's servers might be having issues. Try again later."),
+        UpdateError::Cancelled => String::from("The search was cancelled."),
+        e if e.is_retryable() => format!("Error while talking to Sony's servers, try again later ({e})."),
+        e => format!("Error searching for updates ({e}).")
+    }
+}
+
 #[derive(Default, Deserialize, Serialize)]
 pub struct UpdatesApp {
     #[serde(skip)]
     v: VolatileData,
+    #[serde(skip)]
+    history: PersistentHistory,
     settings: AppSettings
 }
 
 impl eframe::App for UpdatesApp {
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
         eframe::set_value(storage, eframe::APP_KEY, self);
+        eframe::set_value(storage, HISTORY_KEY, &self.history);
+        eframe::set_value(storage, RESULTS_KEY, &(&self.v.update_results, &self.v.results_fetched_at));
     }
 
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
@@ -147,6 +626,10 @@ impl eframe::App for UpdatesApp {
             self.draw_settings_window(ctx);
         }
 
+        if self.v.show_download_queue_window {
+            self.draw_download_queue_window(ctx);
+        }
+
         if self.v.show_mismatch_warning_window {
             self.draw_hash_mismatch_window(ctx);
         }
@@ -155,7 +638,9 @@ impl eframe::App for UpdatesApp {
 
         // Check the status of the search promise.
         self.handle_search_promise(&mut toasts);
+        self.handle_region_search_promise(&mut toasts);
         // Check in on active downloads.
+        self.handle_pending_batch(&mut toasts);
         self.handle_download_promises(&mut toasts);
         self.handle_merge_promises(&mut toasts);
 
@@ -163,13 +648,16 @@ impl eframe::App for UpdatesApp {
             self.show_notifications(msg, level);
         }
 
+        self.update_window_title(ctx);
+        self.update_window_geometry(ctx);
+
         ctx.request_repaint();
         self.v.toasts.show(ctx);
     }
 }
 
 impl UpdatesApp {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>, config_toml: Option<PathBuf>) -> Self {
         let mut fonts = egui::FontDefinitions::default();
 
         fonts.font_data.insert(
@@ -187,54 +675,196 @@ impl UpdatesApp {
 
         cc.egui_ctx.set_fonts(fonts);
 
-        if let Some(storage) = cc.storage {
-            eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default()
+        let mut app: UpdatesApp = {
+            if let Some(storage) = cc.storage {
+                let mut app: UpdatesApp = eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+                app.history = eframe::get_value(storage, HISTORY_KEY).unwrap_or_default();
+
+                if let Some((results, fetched_at)) = eframe::get_value::<(Vec<UpdateInfo>, HashMap<String, u64>)>(storage, RESULTS_KEY) {
+                    info!("Loaded {} cached search result(s)", results.len());
+                    app.v.update_results = results;
+                    app.v.results_fetched_at = fetched_at;
+                    app.refresh_duplicate_packages();
+                }
+
+                app
+            }
+            else {
+                Default::default()
+            }
+        };
+
+        let explicit_config = config_toml.is_some();
+        let config_path = config_toml.unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_TOML));
+
+        if config_path.exists() {
+            info!("Loading settings from {:?}", config_path);
+
+            match std::fs::read_to_string(&config_path) {
+                Ok(contents) => {
+                    match app.settings.merge_toml(&config_path, &contents) {
+                        Ok(settings) => app.settings = settings,
+                        Err(e) => error!("Failed to parse settings from {:?}: {e}", config_path)
+                    }
+                }
+                Err(e) => error!("Failed to read settings from {:?}: {e}", config_path)
+            }
         }
-        else {
-            Default::default()
+        else if explicit_config {
+            warn!("Settings file {:?} doesn't exist, using stored settings", config_path);
+        }
+
+        app.v.config_toml_path = config_path;
+
+        if let Some(proxy) = app.settings.proxy_url.clone() {
+            if let Err(e) = validate_proxy_url(&proxy) {
+                error!("Invalid proxy URL {proxy}: {e}");
+                app.show_notifications(format!("The proxy URL {proxy} isn't valid, check it in the settings. Requests will fail until it's fixed."), ToastLevel::Error);
+            }
         }
+
+        app
     }
 
-    fn handle_search_promise(&mut self, toasts: &mut Vec<(String, ToastLevel)>) -> Option<()> {
-        let is_ready = {
-            let promise = self.v.search_promise.as_ref()?;
-            promise.ready().is_some()
-        };
+    fn handle_search_promise(&mut self, toasts: &mut Vec<(String, ToastLevel)>) {
+        let mut finished = Vec::new();
 
-        if is_ready {
-            let promise = self.v.search_promise.take()?;
-            let promise_ready = promise.block_and_take();
+        for (i, search) in self.v.search_promises.iter_mut().enumerate() {
+            while let Ok(SearchStatus::ManifestProgress(fetched, total)) = search.progress_rx.try_recv() {
+                search.progress = Some((fetched, total));
+            }
+
+            if search.promise.ready().is_some() {
+                finished.push(i);
+            }
+        }
 
-            match promise_ready {
+        // Removed back to front, so the indices of the remaining ones stay valid.
+        for i in finished.into_iter().rev() {
+            let search = self.v.search_promises.remove(i);
+
+            match search.promise.block_and_take() {
                 Ok(update_info) => {
                     info!("Received search results for serial {}", update_info.title_id);
-                    self.v.update_results.push(update_info);
+                    let title_id = update_info.title_id.clone();
+
+                    // Searching for a serial that's already listed refreshes its results, however it was typed.
+                    if self.store_result(update_info) {
+                        toasts.push((format!("Updated results for {title_id}"), ToastLevel::Info));
+                    }
                 }
                 Err(ref e) => {
-                    match e {
-                        UpdateError::UnhandledErrorResponse(e) => {
-                            toasts.push((format!("Unexpected error received in a response from PSN ({e})."), ToastLevel::Error));
-                        }
-                        UpdateError::InvalidSerial => {
-                            toasts.push((String::from("The provided serial didn't give any results, double-check your input."), ToastLevel::Error));
-                        }
-                        UpdateError::NoUpdatesAvailable => {
-                            toasts.push((String::from("The provided serial doesn't have any available updates."), ToastLevel::Error));
-                        }
-                        UpdateError::Reqwest(e) => {
-                            toasts.push((format!("There was an error completing the request ({e})."), ToastLevel::Error));
-                        }
-                        UpdateError::XmlParsing(e) => {
-                            toasts.push((format!("Error parsing response from Sony, try again later ({e})."), ToastLevel::Error));
+                    toasts.push((format!("{}: {}", search.serial, search_error_message(e)), ToastLevel::Error));
+                    error!("Error received from updates query for {}: {:?}", search.serial, e);
+
+                    if e.is_retryable() {
+                        self.v.retryable_search = Some(search.serial);
+                    }
+                }
+            }
+        }
+
+        while self.can_start_search() {
+            match self.v.queued_searches.pop_front() {
+                Some(serial) => {
+                    info!("Fetching updates for '{serial}' (queued)");
+                    self.spawn_search(serial);
+                }
+                None => break
+            }
+        }
+    }
+
+    // Queues a search for each serial in a text file, one per line. Empty lines and ones starting with # are skipped,
+    // as are serials that already have results or are being searched for.
+    fn import_serials(&mut self, path: &Path) -> std::io::Result<usize> {
+        let contents = std::fs::read_to_string(path)?;
+        let mut queued = 0;
+
+        for serial in parse_serial_list(&contents) {
+            let already_known = self.v.update_results.iter().any(| r | r.title_id == serial)
+                || self.v.search_promises.iter().any(| s | parse_title_id(&s.serial) == serial)
+                || self.v.queued_searches.contains(&serial)
+            ;
+
+            if !already_known {
+                self.v.queued_searches.push_back(serial);
+                queued += 1;
+            }
+        }
+
+        info!("Queued {queued} serial(s) from {:?} for search", path);
+        Ok(queued)
+    }
+
+    fn handle_region_search_promise(&mut self, toasts: &mut Vec<(String, ToastLevel)>) -> Option<()> {
+        let is_ready = {
+            let promise = self.v.region_search_promise.as_ref()?;
+            promise.ready().is_some()
+        };
+
+        if is_ready {
+            let promise = self.v.region_search_promise.take()?;
+            let mut found = 0;
+
+            for (serial, result) in promise.block_and_take() {
+                match result {
+                    Ok(update_info) => {
+                        if self.v.update_results.iter().any(| r | r.title_id == update_info.title_id) {
+                            continue;
                         }
-                        UpdateError::ManifestParsing(e) => {
-                            toasts.push((format!("Error parsing manifest response from Sony, try again later ({e})."), ToastLevel::Error));
+
+                        info!("Received search results for regional variant {}", update_info.title_id);
+                        self.store_result(update_info);
+                        found += 1;
+                    }
+                    // Most regional variants don't exist, so these are expected.
+                    Err(UpdateError::InvalidSerial) => {
+                        info!("Regional variant {serial} doesn't exist");
+                    }
+                    Err(ref e) => {
+                        toasts.push((format!("{serial}: {}", search_error_message(e)), ToastLevel::Error));
+                        error!("Error received from updates query for regional variant {serial}: {:?}", e);
+                    }
+                }
+            }
+
+            if found == 0 {
+                toasts.push((String::from("No updates were found for other regions."), ToastLevel::Info));
+            }
+        }
+
+        Some(())
+    }
+
+    fn handle_pending_batch(&mut self, toasts: &mut Vec<(String, ToastLevel)>) -> Option<()> {
+        let is_ready = {
+            let (promise, _) = self.v.pending_batch.as_ref()?;
+            promise.ready().is_some()
+        };
+
+        if is_ready {
+            let (promise, batch) = self.v.pending_batch.take()?;
+
+            if promise.block_and_take() {
+                let request_delay = Duration::from_millis(self.settings.request_delay_ms);
+                let mut start_delay = Duration::ZERO;
+
+                for (title_id, title, pkg) in batch {
+                    if self.get_active_download(&title_id, &pkg).is_none() {
+                        let download = self.start_download(title_id, title, pkg, start_delay);
+                        self.add_download(download);
+
+                        if !request_delay.is_zero() {
+                            start_delay += crate::utils::jittered(request_delay);
                         }
                     }
-        
-                    error!("Error received from updates query: {:?}", e);
                 }
             }
+            else {
+                error!("Connectivity probe failed, not queueing {} download(s)", batch.len());
+                toasts.push((String::from("No network connection detected. Check your connection and try again."), ToastLevel::Error));
+            }
         }
 
         Some(())
@@ -242,14 +872,36 @@ impl UpdatesApp {
 
     fn handle_download_promises(&mut self, toasts: &mut Vec<(String, ToastLevel)>) {
         let mut entries_to_remove = Vec::new();
+        let mut titles_to_stop = Vec::new();
+        let mut completed = Vec::new();
 
         for (i, download) in self.v.download_queue.iter_mut().enumerate() {
             if let Ok(status) = download.progress_rx.try_recv() {
+                download.queued = false;
+
                 if let DownloadStatus::Progress(progress) = status {
                     // info!("Received {progress} bytes for active download ({} {})", download.id, download.version);
                     download.progress += progress;
                 }
 
+                if let DownloadStatus::Speed { bytes_per_sec, .. } = status {
+                    download.record_speed(bytes_per_sec);
+                }
+
+                // Whatever was already on disk counts as progress.
+                if let DownloadStatus::Resuming(offset) = status {
+                    download.progress = offset;
+                }
+
+                download.retry_deadline = match status {
+                    DownloadStatus::Retrying { delay, .. } => {
+                        // Don't let the speed from before the interruption count towards the new attempt.
+                        download.speed_samples.clear();
+                        Some(Instant::now() + delay)
+                    }
+                    _ => None
+                };
+
                 download.last_received_status = status;
             }
 
@@ -257,6 +909,7 @@ impl UpdatesApp {
             if let Some(r) = download.promise.ready() {
                 // Queue up for removal.
                 entries_to_remove.push(i);
+                self.v.batch_finished_size += download.size;
 
                 match r {
                     Ok(_) => {
@@ -264,18 +917,32 @@ impl UpdatesApp {
 
                         // Add this download to the happy list of successful downloads.
                         toasts.push((format!("{} v{} downloaded successfully!", &download.title_id, &download.pkg_id), ToastLevel::Success));
-                        self.v.completed_downloads.push((download.title_id.clone(), download.pkg_id.clone()));
+                        self.history.push_completed(download.title_id.clone(), download.pkg_id.clone());
+                        completed.push((download.title_id.clone(), download.pkg_id.clone()));
                     }
                     Err(e) => {
                         // Add this download to the sad list of failed downloads and show the error window.
-                        self.v.failed_downloads.push((download.title_id.clone(), download.pkg_id.clone()));
+                        self.history.push_failed(download.title_id.clone(), download.pkg_id.clone());
+
+                        if self.settings.stop_on_first_error && !matches!(e, DownloadError::Cancelled) && !titles_to_stop.contains(&download.title_id) {
+                            titles_to_stop.push(download.title_id.clone());
+                        }
 
                         match e {
-                            DownloadError::HashMismatch(short_on_data) => {
+                            DownloadError::HashMismatch { short_on_data, expected, actual } => {
                                 toasts.push((format!("Failed to download {} v{}: Hash mismatch.", download.title_id, download.pkg_id), ToastLevel::Error));
 
                                 if *short_on_data {
+                                    let checked = self.v.update_results.iter()
+                                        .filter(| u | u.title_id == download.title_id)
+                                        .flat_map(| u | u.packages.iter())
+                                        .find(| pkg | pkg.id() == download.pkg_id)
+                                        .map(| pkg | if pkg.sha1sum.is_empty() { String::from("size") } else { pkg.hash_algorithm.to_string() })
+                                        .unwrap_or_else(|| String::from("hash"))
+                                    ;
+
                                     self.v.show_mismatch_warning_window = true;
+                                    self.v.mismatch_hashes = Some((checked, expected.clone(), actual.clone()));
                                 }
                             }
                             DownloadError::Tokio(_) => {
@@ -284,6 +951,18 @@ impl UpdatesApp {
                             DownloadError::Reqwest(_) => {
                                 toasts.push((format!("Failed to download {} v{}. Check the log for details.", download.title_id, download.pkg_id), ToastLevel::Error));
                             }
+                            DownloadError::IoWithPath { .. } => {
+                                toasts.push((format!("Failed to download {} v{}: {e}.", download.title_id, download.pkg_id), ToastLevel::Error));
+                            }
+                            DownloadError::TimedOut => {
+                                toasts.push((format!("Download of {} v{} timed out, try again later.", download.title_id, download.pkg_id), ToastLevel::Error));
+                            }
+                            DownloadError::Cancelled => {
+                                toasts.push((format!("Download of {} v{} was cancelled.", download.title_id, download.pkg_id), ToastLevel::Info));
+                            }
+                            DownloadError::InsufficientDiskSpace { required, available } => {
+                                toasts.push((format!("Not enough disk space to download {} v{} ({} required, {} available).", download.title_id, download.pkg_id, ByteSize::b(*required), ByteSize::b(*available)), ToastLevel::Error));
+                            }
                         }
 
                         error!("Error received from pkg download ({} {}): {:?}", download.title_id, download.pkg_id, e);
@@ -295,62 +974,346 @@ impl UpdatesApp {
         for index in entries_to_remove.into_iter().rev() {
             self.v.download_queue.remove(index);
         }
-    }
 
-    fn handle_merge_promises(&mut self, toasts: &mut Vec<(String, ToastLevel)>) {
-        let mut finished_merge_indexes: Vec<usize> = Vec::new();
-        for i in 0..self.v.merge_queue.len() {
-            let merge = &mut self.v.merge_queue[i];
-            if let Ok(status) = merge.progress_rx.try_recv() {
-                if let MergeStatus::PartProgress(progress) = status {
-                    merge.part_progress = progress;
+        for title_id in titles_to_stop {
+            self.stop_title_downloads(&title_id, toasts);
+        }
+
+        if self.settings.auto_merge_after_download {
+            let mut titles: Vec<&String> = Vec::new();
+            for (title_id, _) in completed.iter() {
+                if !titles.contains(&title_id) {
+                    titles.push(title_id);
                 }
+            }
 
-                merge.last_received_status = status;
+            for title_id in titles {
+                self.auto_merge_title(title_id, toasts);
             }
+        }
 
-            if let Some(result) = merge.promise.ready() {
-                match result {
-                    Ok(_) => {
-                        info!("Merge completed for {}", &merge.title_id);
+        if self.settings.save_changeinfo {
+            for (title_id, pkg_id) in completed {
+                self.save_changeinfo(&title_id, &pkg_id);
+            }
+        }
+    }
 
-                        toasts.push((format!("{} merged successfully!", &merge.title_id), ToastLevel::Success));
-                        self.v.completed_merges.push(merge.title_id.clone());
-                    }
-                    Err(e) => {
-                        self.v.failed_merges.push(merge.title_id.clone());
+    // Queues a merge for a split PS4 update once every one of its parts is in the completed list.
+    fn auto_merge_title(&mut self, title_id: &str, toasts: &mut Vec<(String, ToastLevel)>) {
+        let update = match self.v.update_results.iter().find(| u | u.title_id == title_id) {
+            Some(update) => update,
+            None => return
+        };
 
-                        match e {
-                            MergeError::FilepathMismatch(_) | MergeError::PackagesUnmergable(_) | MergeError::FileMergeFailure => {
-                                toasts.push((format!("Failed to merge {}. Check the log for details.", merge.title_id), ToastLevel::Error));
-                            }
-                        }
+        if update.platform_variant != utils::PlaformVariant::PS4 || update.packages.len() < 2 {
+            return;
+        }
 
-                        error!("Could not merge files for {}, reason: {:?}", merge.title_id, e);
-                    }
-                }
+        if !update.packages.iter().all(| pkg | pkg.part_number.is_some()) {
+            return;
+        }
 
-                finished_merge_indexes.push(i);
-            }
+        let all_parts_completed = update.packages.iter().all(| pkg | {
+            self.history.completed_downloads.iter().any(| (id, pkg_id) | id == title_id && pkg_id == &pkg.id())
+        });
+
+        if !all_parts_completed || self.title_has_active_downloads(title_id) {
+            return;
         }
 
-        for idx in finished_merge_indexes.iter().rev() {
-            self.v.merge_queue.remove(*idx);
+        if self.get_active_merge(title_id).is_some() || self.v.completed_merges.iter().any(| id | id == title_id) {
+            info!("All parts of {title_id} are downloaded, but it's already been merged or is being merged");
+            return;
         }
+
+        info!("All parts of {title_id} are downloaded, merging them");
+        toasts.push((format!("Auto-merging parts for {title_id}..."), ToastLevel::Info));
+
+        let merge = self.start_merge_parts(update.clone());
+        self.v.merge_queue.push(merge);
     }
 
-    fn start_download(&self, serial: String, title: String, pkg: PackageInfo) -> ActiveDownload {
-        let (tx, rx) = tokio::sync::mpsc::channel(10);
-        let id = serial.clone();
-        let pkg_id = pkg.id();
-        let download_size = pkg.size;
-        let download_path = self.settings.pkg_download_path.clone();
+    fn save_changeinfo(&mut self, title_id: &str, pkg_id: &str) {
+        let update = match self.v.update_results.iter().find(| u | u.title_id == title_id) {
+            Some(update) => update,
+            None => return
+        };
 
-        let _guard = self.v.rt.enter();
+        let pkg = match update.packages.iter().find(| p | p.id() == pkg_id) {
+            Some(pkg) => pkg.clone(),
+            None => return
+        };
 
-        let download_promise = Promise::spawn_async(
+        if pkg.changeinfo_url.is_empty() {
+            return;
+        }
+
+        // Parts of a split update share their patch notes, which only need to be fetched once.
+        let changeinfo_key = (update.title_id.clone(), pkg.version.clone());
+        if self.v.saved_changeinfo.contains(&changeinfo_key) {
+            return;
+        }
+
+        let serial = update.title_id.clone();
+        let title = update.title();
+        let download_path = self.settings.pkg_download_path.clone();
+        let strict_names = self.settings.strict_file_names;
+        let client = match self.search_client() {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Failed to create http client to fetch patch notes: {e}");
+                return;
+            }
+        };
+
+        self.v.saved_changeinfo.push(changeinfo_key);
+
+        // Nothing waits on the notes, they're written whenever they arrive.
+        self.v.rt.spawn(async move {
+            pkg.save_changeinfo(&client, download_path, &serial, &title, strict_names).await;
+        });
+    }
+
+    // Dropping a download's status receiver makes it stop with DownloadError::Cancelled.
+    fn stop_title_downloads(&mut self, title_id: &str, toasts: &mut Vec<(String, ToastLevel)>) {
+        let (stopped, remaining): (Vec<ActiveDownload>, Vec<ActiveDownload>) = std::mem::take(&mut self.v.download_queue)
+            .into_iter()
+            .partition(| d | d.title_id == title_id)
+        ;
+
+        self.v.download_queue = remaining;
+
+        if let Some((_, pending)) = self.v.pending_batch.as_mut() {
+            pending.retain(| (id, _, _) | id != title_id);
+        }
+
+        if stopped.is_empty() {
+            return;
+        }
+
+        warn!("A download for {title_id} failed, stopping its other {} download(s)", stopped.len());
+
+        for download in stopped {
+            self.v.batch_finished_size += download.size;
+            self.history.push_failed(download.title_id, download.pkg_id);
+        }
+
+        toasts.push((format!("Stopped the remaining downloads for {title_id} after a failure."), ToastLevel::Warning));
+    }
+
+    fn handle_merge_promises(&mut self, toasts: &mut Vec<(String, ToastLevel)>) {
+        let mut finished_merge_indexes: Vec<usize> = Vec::new();
+        for i in 0..self.v.merge_queue.len() {
+            let merge = &mut self.v.merge_queue[i];
+            // Byte progress comes in a lot quicker than the parts, catch up with all of it every frame.
+            while let Ok(status) = merge.progress_rx.try_recv() {
+                merge.record_status(&status);
+                merge.last_received_status = status;
+            }
+
+            if let Some(result) = merge.promise.ready() {
+                match result {
+                    Ok(_) => {
+                        info!("Merge completed for {}", &merge.title_id);
+
+                        toasts.push((format!("{} merged successfully!", &merge.title_id), ToastLevel::Success));
+                        self.v.completed_merges.push(merge.title_id.clone());
+                    }
+                    Err(e) => {
+                        self.v.failed_merges.push(merge.title_id.clone());
+
+                        match e {
+                            MergeError::FilepathMismatch(_) | MergeError::PackagesUnmergable(_) | MergeError::FileMergeFailure
+                            | MergeError::DuplicatePart(_) | MergeError::MissingPart(_) => {
+                                toasts.push((format!("Failed to merge {}. Check the log for details.", merge.title_id), ToastLevel::Error));
+                            }
+                            MergeError::MergedFileMismatch { expected, got } => {
+                                toasts.push((format!("The merged pkg for {} failed verification (expected {}, got {}). Try downloading the parts again.", merge.title_id, expected, got), ToastLevel::Error));
+                            }
+                            MergeError::InsufficientDiskSpace { required, available } => {
+                                toasts.push((format!("Not enough disk space to merge {} ({} required, {} available).", merge.title_id, ByteSize::b(*required), ByteSize::b(*available)), ToastLevel::Error));
+                            }
+                        }
+
+                        error!("Could not merge files for {}, reason: {:?}", merge.title_id, e);
+                    }
+                }
+
+                finished_merge_indexes.push(i);
+            }
+        }
+
+        for idx in finished_merge_indexes.iter().rev() {
+            self.v.merge_queue.remove(*idx);
+        }
+    }
+
+    fn search_client(&mut self) -> Result<ReqwestClient, UpdateError> {
+        if let Some(client) = self.v.search_client.as_ref() {
+            return Ok(client.clone());
+        }
+
+        let client = ReqwestClient::new(&self.settings.client_config()).map_err(UpdateError::Reqwest)?;
+        self.v.search_client = Some(client.clone());
+
+        Ok(client)
+    }
+
+    // If the client can't be built, downloads build their own and report the error themselves.
+    fn shared_download_config(&mut self) -> DownloadConfig {
+        if let Some(config) = self.v.download_config.as_ref() {
+            return config.clone();
+        }
+
+        let mut config = self.settings.download_config();
+        config.rate_limit = self.rate_limiter();
+
+        match config.clone().with_shared_client() {
+            Ok(config) => {
+                self.v.download_config = Some(config.clone());
+                config
+            }
+            Err(e) => {
+                error!("Failed to create download client: {e}");
+                config
+            }
+        }
+    }
+
+    fn rate_limiter(&mut self) -> Option<Arc<RateLimiter>> {
+        // A hand-edited settings file could have 0, which would stall every download instead of limiting them.
+        let kb_per_sec = self.settings.max_download_rate_kb_per_sec.filter(| rate | *rate > 0)?;
+        let bytes_per_sec = ByteSize::kb(kb_per_sec).as_u64();
+
+        match self.v.rate_limiter.as_ref() {
+            Some(limiter) if limiter.bytes_per_sec() == bytes_per_sec => Some(limiter.clone()),
+            _ => {
+                let limiter = Arc::new(RateLimiter::new(bytes_per_sec));
+                self.v.rate_limiter = Some(limiter.clone());
+                Some(limiter)
+            }
+        }
+    }
+
+    fn reset_http_clients(&mut self) {
+        self.v.search_client = None;
+        self.v.download_config = None;
+    }
+
+    fn spawn_search(&mut self, serial: String) {
+        let (tx, rx) = tokio::sync::mpsc::channel(10);
+        let client = self.search_client();
+
+        if self.v.retryable_search.as_ref() == Some(&serial) {
+            self.v.retryable_search = None;
+        }
+
+        let _guard = self.v.rt.enter();
+
+        let search_serial = serial.clone();
+        let promise = Promise::spawn_async(
+            async move {
+                let client = client?;
+                UpdateInfo::get_info_with_progress(&client, search_serial, Some(tx)).await
+            }
+        );
+
+        self.v.search_promises.push(ActiveSearch { serial, progress: None, promise, progress_rx: rx });
+    }
+
+    // Adds a title's results to the list, replacing the ones already there for it if any.
+    // Returns whether existing results were replaced.
+    fn store_result(&mut self, update_info: UpdateInfo) -> bool {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(| d | d.as_secs()).unwrap_or(0);
+        let title_id = parse_title_id(&update_info.title_id);
+        self.v.results_fetched_at.insert(update_info.title_id.clone(), now);
+
+        let replaced = match self.v.update_results.iter_mut().find(| r | parse_title_id(&r.title_id) == title_id) {
+            Some(existing) => {
+                if existing.title_id != update_info.title_id {
+                    self.v.results_fetched_at.remove(&existing.title_id);
+                }
+
+                *existing = update_info;
+                true
+            }
+            None => {
+                self.v.update_results.push(update_info);
+                false
+            }
+        };
+
+        self.refresh_duplicate_packages();
+        replaced
+    }
+
+    // Has to be called whenever update_results changes, it's too slow to redo on every frame.
+    fn refresh_duplicate_packages(&mut self) {
+        self.v.duplicate_packages = find_duplicate_packages(&self.v.update_results);
+    }
+
+    // Seconds since a title's results were fetched, if that's known.
+    fn result_age(&self, title_id: &str) -> Option<u64> {
+        let fetched_at = self.v.results_fetched_at.get(title_id)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+
+        Some(now.saturating_sub(*fetched_at))
+    }
+
+    fn can_start_search(&self) -> bool {
+        self.v.search_promises.len() < self.settings.max_concurrent_downloads.clamp(1, MAX_CONCURRENT_DOWNLOADS_LIMIT)
+    }
+
+    // Dropping the receivers makes the searches stop before fetching their next manifest.
+    fn cancel_searches(&mut self) {
+        info!("Cancelling {} running search(es) and {} queued one(s)", self.v.search_promises.len(), self.v.queued_searches.len());
+        self.v.search_promises.clear();
+        self.v.queued_searches.clear();
+    }
+
+    // Queries the variants one after the other, to avoid hammering Sony's servers.
+    fn spawn_region_search(&mut self, serials: Vec<String>) -> Result<Promise<Vec<(String, Result<UpdateInfo, UpdateError>)>>, UpdateError> {
+        let client = self.search_client()?;
+
+        let _guard = self.v.rt.enter();
+
+        Ok(Promise::spawn_async(
+            async move {
+                let mut results = Vec::new();
+
+                for serial in serials {
+                    let result = UpdateInfo::get_info_with(&client, serial.clone()).await;
+                    results.push((serial, result));
+                }
+
+                results
+            }
+        ))
+    }
+
+    // Downloads in a batch can be started with a delay, so they don't all hit Sony's servers at once.
+    fn start_download(&mut self, serial: String, title: String, pkg: PackageInfo, start_delay: Duration) -> ActiveDownload {
+        let (tx, rx) = tokio::sync::mpsc::channel(10);
+        let id = serial.clone();
+        let pkg_id = pkg.id();
+        let download_size = pkg.size;
+        let download_path = self.settings.pkg_download_path.clone();
+        let download_config = self.shared_download_config();
+        let semaphore = self.download_semaphore();
+
+        let _guard = self.v.rt.enter();
+
+        let download_promise = Promise::spawn_async(
             async move {
-                pkg.start_download(tx, download_path, serial, title).await
+                // The semaphore is never closed, but a download shouldn't be lost if it somehow were.
+                let _permit = semaphore.acquire_owned().await.ok();
+
+                if !start_delay.is_zero() {
+                    tokio::time::sleep(start_delay).await;
+                }
+
+                pkg.start_download(tx, download_path, serial, title, download_config).await
             }
         );
 
@@ -360,7 +1323,10 @@ impl UpdatesApp {
 
             size: download_size,
             progress: 0,
+            speed_samples: VecDeque::with_capacity(SPEED_SAMPLE_CAPACITY + 1),
+            queued: true,
             last_received_status: DownloadStatus::Verifying,
+            retry_deadline: None,
 
             promise: download_promise,
             progress_rx: rx
@@ -370,13 +1336,21 @@ impl UpdatesApp {
     fn start_merge_parts(&self, update_info: UpdateInfo) -> ActiveMerge {
         let (tx, rx) = tokio::sync::mpsc::channel(10);
         let download_path = self.settings.pkg_download_path.clone();
+        let strict_names = self.settings.strict_file_names;
+        let name_template = self.settings.merged_name_template.clone();
+        let scratch_path = self.settings.scratch_path.clone();
+        let delete_parts = self.settings.auto_delete_parts_after_merge;
         let title_id = update_info.title_id.clone();
+        let pending_parts: Vec<(usize, u64)> = update_info.packages.iter()
+            .filter_map(| pkg | pkg.part_number.map(| part | (part, pkg.size)))
+            .collect();
+        let total_bytes = pending_parts.iter().map(| (_, size) | size).sum();
 
         let _guard = self.v.rt.enter();
 
         let merge_promise = Promise::spawn_async(
             async move {
-                update_info.merge_parts(tx, &download_path).await
+                update_info.merge_parts(tx, &download_path, strict_names, &name_template, scratch_path.as_deref(), delete_parts).await
             }
         );
 
@@ -384,6 +1358,9 @@ impl UpdatesApp {
             title_id,
 
             part_progress: 0,
+            bytes_merged: 0,
+            total_bytes,
+            pending_parts,
             last_received_status: MergeStatus::PartProgress(0),
 
             promise: merge_promise,
@@ -391,6 +1368,93 @@ impl UpdatesApp {
         }
     }
 
+    fn update_window_title(&mut self, ctx: &egui::Context) {
+        let title = {
+            if self.v.download_queue.is_empty() {
+                self.v.batch_download_count = 0;
+                self.v.batch_total_size = 0;
+                self.v.batch_finished_size = 0;
+
+                String::from("rusty-psn")
+            }
+            else {
+                let in_progress_size = self.v.download_queue.iter()
+                    .map(| d | d.progress.min(d.size))
+                    .sum::<u64>()
+                ;
+
+                let downloaded_size = self.v.batch_finished_size + in_progress_size;
+                let percentage = if self.v.batch_total_size > 0 { downloaded_size * 100 / self.v.batch_total_size } else { 0 };
+                let finished_count = self.v.batch_download_count - self.v.download_queue.len();
+
+                format!("rusty-psn — {}% ({finished_count}/{})", percentage.min(100), self.v.batch_download_count)
+            }
+        };
+
+        // Only send the command when the title actually changes, instead of every frame.
+        if title != self.v.window_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.v.window_title = title;
+        }
+    }
+
+    fn update_window_geometry(&mut self, ctx: &egui::Context) {
+        let (outer_rect, inner_rect, monitor_size) = ctx.input(| i | {
+            let viewport = i.viewport();
+            (viewport.outer_rect, viewport.inner_rect, viewport.monitor_size)
+        });
+
+        if !self.v.window_geometry_restored {
+            self.v.window_geometry_restored = true;
+
+            if let Some(geometry) = self.settings.window_geometry {
+                match monitor_size {
+                    Some(monitor_size) => {
+                        let geometry = geometry.clamped_to(monitor_size);
+                        info!("Restoring window geometry: {:?} {:?}", geometry.position, geometry.size);
+
+                        ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(egui::pos2(geometry.position[0], geometry.position[1])));
+                        ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(geometry.size[0], geometry.size[1])));
+                    }
+                    None => {
+                        // Without knowing the monitor, the position can't be checked. The size is safe to restore.
+                        let size = egui::vec2(geometry.size[0].max(MIN_WINDOW_SIZE[0]), geometry.size[1].max(MIN_WINDOW_SIZE[1]));
+                        ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(size));
+                    }
+                }
+            }
+
+            return;
+        }
+
+        // Some platforms (Wayland) don't report the window position, in which case nothing is stored.
+        if let (Some(outer_rect), Some(inner_rect)) = (outer_rect, inner_rect) {
+            self.settings.window_geometry = Some(WindowGeometry {
+                position: [outer_rect.min.x, outer_rect.min.y],
+                size: [inner_rect.width(), inner_rect.height()]
+            });
+        }
+    }
+
+    // All copy actions go through here, the clipboard isn't available on every setup.
+    fn set_clipboard(&mut self, text: String) -> bool {
+        match self.v.clipboard.as_mut() {
+            Some(clip_ctx) => {
+                match clip_ctx.set_contents(text) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        warn!("Failed to set clipboard contents: {}", e.to_string());
+                        false
+                    }
+                }
+            }
+            None => {
+                warn!("Tried to copy to the clipboard, but it's unavailable");
+                false
+            }
+        }
+    }
+
     fn show_notifications<S: Into<String>>(&mut self, msg: S, level: ToastLevel) {
         let msg = msg.into();
 
@@ -427,54 +1491,150 @@ impl UpdatesApp {
             let input_submitted = serial_input.lost_focus() && ui.input(| i | i.key_pressed(egui::Key::Enter));
 
             serial_input.context_menu(| ui | {
-                ui.add_enabled_ui(self.v.clipboard.is_some(), | ui | {
-                    if let Some(clip_ctx) = self.v.clipboard.as_mut() {
-                        if ui.button("Paste").clicked() {
-                            match clip_ctx.get_contents(){
-                                Ok(contents) => self.v.serial_query.push_str(&contents),
-                                Err(e) => warn!("Failed to paste clipboard contents: {}", e.to_string())
-                            }
+                let paste_btn = ui.add_enabled(self.v.clipboard.is_some(), egui::Button::new("Paste"))
+                    .on_disabled_hover_text(CLIPBOARD_UNAVAILABLE_TEXT);
 
-                            ui.close_menu();
+                if paste_btn.clicked() {
+                    if let Some(clip_ctx) = self.v.clipboard.as_mut() {
+                        match clip_ctx.get_contents(){
+                            Ok(contents) => self.v.serial_query.push_str(&contents),
+                            Err(e) => warn!("Failed to paste clipboard contents: {}", e.to_string())
                         }
+                    }
 
-                        ui.add_enabled_ui(!self.v.serial_query.is_empty(), |ui| {
-                            if ui.button("Clear").clicked() {
-                                self.v.serial_query = String::new();
-                                ui.close_menu();
-                            }
-                        });
+                    ui.close_menu();
+                }
+
+                ui.add_enabled_ui(!self.v.serial_query.is_empty(), |ui| {
+                    if ui.button("Clear").clicked() {
+                        self.v.serial_query = String::new();
+                        ui.close_menu();
                     }
                 });
             });
 
             ui.separator();
             
-            ui.add_enabled_ui(!self.v.serial_query.is_empty() && self.v.search_promise.is_none(), | ui | {
+            let can_start_search = self.can_start_search();
+
+            ui.add_enabled_ui(!self.v.serial_query.is_empty() && can_start_search, | ui | {
                 if !input_submitted && !ui.button("Search for updates").clicked() { return; }
 
-                let already_searched = self.v.update_results.iter().any(|e| e.title_id == parse_title_id(&self.v.serial_query));
-                if already_searched { 
-                    self.show_notifications("Provided title id results already shown", ToastLevel::Info);
+                let already_searching = self.v.search_promises.iter().any(| s | parse_title_id(&s.serial) == parse_title_id(&self.v.serial_query));
+                if already_searching {
+                    self.show_notifications("Provided title id is already being searched for", ToastLevel::Info);
                     return;
                 }
 
                 info!("Fetching updates for '{}'", self.v.serial_query);
 
-                let _guard = self.v.rt.enter();
-                let promise = Promise::spawn_async(UpdateInfo::get_info(self.v.serial_query.clone()));
-                
-                self.v.search_promise = Some(promise);
+                self.spawn_search(self.v.serial_query.clone());
+            });
+
+            if let Some(serial) = self.v.retryable_search.clone() {
+                let retry_btn = ui.add_enabled(can_start_search, egui::Button::new("Retry search"))
+                    .on_hover_text(format!("The search for {serial} failed with an error that might go away by trying again."));
+
+                if retry_btn.clicked() {
+                    info!("Retrying search for '{serial}'");
+                    self.spawn_search(serial);
+                }
+            }
+
+            if !self.v.search_promises.is_empty() {
+                ui.spinner();
+
+                match self.v.search_promises.as_slice() {
+                    [search] => {
+                        if let Some((fetched, total)) = search.progress {
+                            ui.label(format!("Fetched manifest {fetched}/{total}"));
+                        }
+                    }
+                    searches => {
+                        ui.label(format!("{} searches in progress", searches.len()));
+                    }
+                }
+
+                if !self.v.queued_searches.is_empty() {
+                    ui.label(format!("({} queued)", self.v.queued_searches.len()));
+                }
+
+                if ui.button("Cancel").clicked() {
+                    self.cancel_searches();
+                }
+            }
+
+            let import_btn = ui.button("Import from file")
+                .on_hover_text("Search for every serial in a text file, one per line. Lines starting with # are ignored.");
+
+            if import_btn.clicked() {
+                if let Some(path) = rfd::FileDialog::new().add_filter("Text", &["txt"]).pick_file() {
+                    match self.import_serials(&path) {
+                        Ok(queued) => self.show_notifications(format!("Queued {queued} serial(s) for search"), ToastLevel::Info),
+                        Err(e) => {
+                            error!("Failed to read serials from {:?}: {e}", path);
+                            self.show_notifications(format!("Failed to read {}: {e}", path.display()), ToastLevel::Error);
+                        }
+                    }
+                }
+            }
+
+            let regional_variants = get_regional_variants(&parse_title_id(&self.v.serial_query));
+
+            ui.add_enabled_ui(!regional_variants.is_empty() && self.v.region_search_promise.is_none(), | ui | {
+                if ui.button("Search all regions").clicked() {
+                    let variants = regional_variants.into_iter()
+                        .filter(| serial | !self.v.update_results.iter().any(| r | &r.title_id == serial))
+                        .collect::<Vec<String>>()
+                    ;
+
+                    info!("Fetching updates for the regional variants of '{}': {:?}", self.v.serial_query, variants);
+                    match self.spawn_region_search(variants) {
+                        Ok(promise) => self.v.region_search_promise = Some(promise),
+                        Err(e) => {
+                            error!("Failed to create http client to search the regional variants: {e}");
+                            self.show_notifications(search_error_message(&e), ToastLevel::Error);
+                        }
+                    }
+                }
             });
 
+            let filter_text = match self.v.platform_filter {
+                Some(platform) => platform.to_string(),
+                None => String::from("All platforms")
+            };
+
+            egui::ComboBox::from_id_source("platform_filter")
+                .selected_text(filter_text)
+                .show_ui(ui, | ui | {
+                    ui.selectable_value(&mut self.v.platform_filter, None, "All platforms");
+
+                    for platform in utils::PlaformVariant::ALL {
+                        ui.selectable_value(&mut self.v.platform_filter, Some(platform), platform.to_string());
+                    }
+                })
+            ;
+
             ui.add_enabled_ui(!self.v.update_results.is_empty(), | ui | {
                 if ui.button("Clear results").clicked() {
                     self.v.update_results = Vec::new();
+                    self.v.results_fetched_at.clear();
+                    self.refresh_duplicate_packages();
+                }
+            });
+
+            ui.add_enabled_ui(!self.history.failed_downloads.is_empty(), | ui | {
+                if ui.button("Retry all failed").clicked() {
+                    self.retry_failed_downloads();
                 }
             });
 
             ui.separator();
 
+            if ui.button(format!("Downloads ({})", self.v.download_queue.len())).clicked() {
+                self.v.show_download_queue_window = true;
+            }
+
             if ui.button("⚙").clicked() {
                 self.v.modified_settings = self.settings.clone();
                 self.v.show_settings_window = true;
@@ -485,60 +1645,109 @@ impl UpdatesApp {
     fn draw_results_list(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
         egui::ScrollArea::vertical().auto_shrink([false; 2]).show(ui, | ui | {
             for update in self.v.update_results.clone().iter() {
+                if let Some(platform) = self.v.platform_filter {
+                    if !update.matches_platform(platform) {
+                        continue;
+                    }
+                }
+
                 self.draw_result_entry(ctx, ui, update);
             }
         });
     }
 
     fn draw_result_entry(&mut self, ctx: &egui::Context, ui: &mut egui::Ui, update: &UpdateInfo) {
-        let total_updates_size = update.packages.iter()
-            .map(| pkg | pkg.size)
-            .sum::<u64>()
-        ;
-
+        let summary = update.summary();
         let title_id = &update.title_id;
-        let update_count = update.packages.len();
-        let platform_variant = update.platform_variant;
+        let update_count = summary.package_count;
 
         let id = egui::Id::new(format!("pkg_header_{title_id}"));
 
         egui::collapsing_header::CollapsingState::load_with_default_open(ctx, id, false)
             .show_header(ui, | ui | {
-                let title =  update.title();
+                let title = summary.title.clone();
+
+                ui.strong(summary.to_string()).context_menu(| ui | {
+                    let clipboard_available = self.v.clipboard.is_some();
+
+                    let copy_serial = ui.add_enabled(clipboard_available, egui::Button::new("Copy serial"))
+                        .on_disabled_hover_text(CLIPBOARD_UNAVAILABLE_TEXT);
 
-                let collapsing_title = {
-                    if !title.is_empty() {
-                        format!("[{platform_variant}] {title_id} - {title} ({update_count} update(s) - {} total)", ByteSize::b(total_updates_size))
+                    if copy_serial.clicked() {
+                        self.set_clipboard(title_id.clone());
+                        ui.close_menu();
                     }
-                    else {
-                        format!("[{platform_variant}] {title_id} ({update_count} update(s) - {} total)", ByteSize::b(total_updates_size))
+
+                    let copy_title = ui.add_enabled(clipboard_available && !title.is_empty(), egui::Button::new("Copy title"))
+                        .on_disabled_hover_text(CLIPBOARD_UNAVAILABLE_TEXT);
+
+                    if copy_title.clicked() {
+                        self.set_clipboard(title.clone());
+                        ui.close_menu();
                     }
-                };
+                });
+
+                if let Some(age) = self.result_age(title_id) {
+                    ui.weak(format_age(age));
+                }
+
+                let is_refreshing = self.v.search_promises.iter().any(| s | parse_title_id(&s.serial) == *title_id);
+                let refresh_btn = ui.add_enabled(!is_refreshing && self.can_start_search(), egui::Button::new("Refresh"))
+                    .on_hover_text("Search for this title's updates again, in case Sony added new ones.");
 
-                ui.strong(collapsing_title);
+                if refresh_btn.clicked() {
+                    info!("Refreshing the results for {title_id}");
+                    self.spawn_search(title_id.clone());
+                }
+
+                let folder = create_new_pkg_path(&self.settings.pkg_download_path, title_id, &update.title(), self.settings.strict_file_names);
+                let open_folder_btn = ui.add_enabled(folder.is_dir(), egui::Button::new("Open folder"))
+                    .on_hover_text(folder.display().to_string())
+                    .on_disabled_hover_text("Nothing has been downloaded for this title yet");
+
+                if open_folder_btn.clicked() {
+                    if let Err(e) = open_folder(&folder) {
+                        error!("Failed to open {:?}: {e}", folder);
+                        self.show_notifications(format!("Failed to open the download folder: {e}"), ToastLevel::Error);
+                    }
+                }
 
                 ui.separator();
     
                 if ui.button("Download all").clicked() {
                     info!("Downloading all updates for serial {} ({})", title_id, update_count);
+
+                    let version_filter = self.settings.version_filter();
+                    let mut batch = Vec::new();
     
                     for pkg in update.packages.iter() {
+                        if !version_filter.allows(title_id, pkg) {
+                            info!("Skipping update {} for serial {title_id}, excluded by the version filter", pkg.id());
+                            continue;
+                        }
+
                         // Avoid duplicates by checking if there's already a download for this serial and version on the queue.
                         if self.get_active_download(&title_id, pkg).is_none() {
                             info!("Downloading update {} for serial {title_id} (group)", pkg.id());
-                            self.add_download(self.start_download(title_id.to_string(), title.clone(), pkg.clone()));
+                            batch.push((title_id.to_string(), title.clone(), pkg.clone()));
                         }
                     }
+
+                    self.queue_download_batch(batch);
                 }
 
-                if platform_variant != utils::PlaformVariant::PS4 { return; }
+                if summary.platform_variant != utils::PlaformVariant::PS4 { return; }
 
                 let is_multipart = update.packages.len() > 1;
                 let all_pkgs_completed = update.packages.iter().all(|pkg| {
                     return self.pkg_download_status(title_id, pkg) == ActiveDownloadStatus::Completed;
                 });
-                let is_mergable = is_multipart && all_pkgs_completed;
-                let hover_text = if is_multipart {
+                // A part that's being downloaded again could be read halfway through being written.
+                let has_active_downloads = self.title_has_active_downloads(title_id);
+                let is_mergable = is_multipart && all_pkgs_completed && !has_active_downloads;
+                let hover_text = if has_active_downloads {
+                    "Finish the title's downloads before merging"
+                } else if is_multipart {
                     "All parts need to be completed for merge to be available"
                 } else {
                     "This PS4 update is not a multipart update"
@@ -549,7 +1758,22 @@ impl UpdatesApp {
                 match self.title_merge_status(update) {
                     ActiveMergeStatus::Merging(progress) => {
                         ui.label(egui::RichText::new("Merging parts...").color(egui::Rgba::from_rgb(1.0, 1.0, 0.6)));
-                        ui.add(egui::ProgressBar::new(progress).show_percentage());
+
+                        let progress_bar = match self.get_active_merge(title_id) {
+                            Some(merge) => egui::ProgressBar::new(progress)
+                                .text(format!("{} / {}", ByteSize::b(merge.bytes_merged), ByteSize::b(merge.total_bytes))),
+                            None => egui::ProgressBar::new(progress).show_percentage()
+                        };
+
+                        ui.add(progress_bar);
+                    },
+                    ActiveMergeStatus::Verifying => {
+                        ui.label(egui::RichText::new("Verifying merged file...").color(egui::Rgba::from_rgb(1.0, 1.0, 0.6)));
+                        ui.spinner();
+                    },
+                    ActiveMergeStatus::CleaningUp => {
+                        ui.label(egui::RichText::new("Deleting parts...").color(egui::Rgba::from_rgb(1.0, 1.0, 0.6)));
+                        ui.spinner();
                     },
                     ActiveMergeStatus::Merged => {
                         ui.label(egui::RichText::new("Parts merged").color(egui::Rgba::from_rgb(0.0, 1.0, 0.0)));
@@ -561,12 +1785,39 @@ impl UpdatesApp {
                 }
 
                 if merge_btn.clicked() {
-                    self.v.merge_queue.push(self.start_merge_parts(update.clone()));
+                    if self.title_has_active_downloads(title_id) {
+                        self.show_notifications("Finish the title's downloads before merging.", ToastLevel::Warning);
+                    }
+                    else {
+                        self.v.merge_queue.push(self.start_merge_parts(update.clone()));
+                    }
                 }
             })
             .body(| ui | {
                 ui.add_space(5.0);
 
+                // Each package carries its own title, which can differ between localized variants.
+                // Only the first one is used in the header, so list the rest here.
+                let mut titles: Vec<&String> = Vec::new();
+                for title in update.titles.iter() {
+                    if !titles.contains(&title) {
+                        titles.push(title);
+                    }
+                }
+
+                if titles.len() > 1 {
+                    egui::CollapsingHeader::new(format!("Alternate titles ({})", titles.len()))
+                        .id_source(format!("alt_titles_{title_id}"))
+                        .show(ui, | ui | {
+                            for title in titles {
+                                ui.label(title);
+                            }
+                        })
+                    ;
+
+                    ui.add_space(5.0);
+                }
+
                 for pkg in update.packages.iter() {
                     self.draw_entry_pkg(ui, pkg, title_id, update.title());
 
@@ -583,28 +1834,85 @@ impl UpdatesApp {
         ui.group(| ui | {
             ui.strong(format!("Package Version: {}", pkg.id()));
             ui.label(format!("Size: {}", ByteSize::b(pkg.size)));
-            ui.label(format!("SHA-1 hashsum: {}", pkg.sha1sum));
+            ui.label(format!("{} hashsum: {}", pkg.hash_algorithm, pkg.sha1sum));
+
+            // For downloading through something else, like a download manager or wget.
+            let copy_url = ui.add_enabled(self.v.clipboard.is_some(), egui::Button::new("Copy URL"))
+                .on_hover_text(&pkg.url)
+                .on_disabled_hover_text(CLIPBOARD_UNAVAILABLE_TEXT);
+
+            if copy_url.clicked() && self.set_clipboard(pkg.url.clone()) {
+                self.show_notifications(format!("Copied the URL of {title_id} v{}.", pkg.id()), ToastLevel::Info);
+            }
+
             if pkg.offset > 0 {
                 ui.label(format!("Part offset: {}", pkg.offset));
             }
 
+            if let Some(firmware) = pkg.firmware_version() {
+                ui.label(format!("Requires firmware: {firmware}"));
+            }
+
+            if !self.settings.version_filter().allows(title_id, pkg) {
+                ui.label(egui::RichText::new("Skipped by the version filter when downloading all").color(egui::Color32::GRAY));
+            }
+
+            if let Some(duplicates) = self.v.duplicate_packages.get(&pkg.sha1sum.to_lowercase()) {
+                let others = duplicates.iter()
+                    .filter(| (serial, _) | serial != title_id)
+                    .map(| (serial, pkg_id) | format!("{serial} v{pkg_id}"))
+                    .collect::<Vec<String>>()
+                ;
+
+                ui.label(egui::RichText::new("Same file as another result").color(egui::Rgba::from_rgb(0.6, 0.8, 1.0)))
+                    .on_hover_text(format!("Identical to {}. There's no need to download it twice.", others.join(", ")))
+                ;
+            }
+
+            if self.settings.developer_mode {
+                egui::CollapsingHeader::new("Details")
+                    .id_source(format!("pkg_details_{title_id}_{}", pkg.id()))
+                    .show(ui, | ui | {
+                        ui.monospace(format!("url: {}", pkg.url));
+                        ui.monospace(format!("size: {}", pkg.size));
+                        ui.monospace(format!("version: {}", pkg.version));
+                        ui.monospace(format!("sha1sum: {}", pkg.sha1sum));
+                        ui.monospace(format!("hash_algorithm: {:?}", pkg.hash_algorithm));
+                        ui.monospace(format!("hash_whole_file: {}", pkg.hash_whole_file));
+                        ui.monospace(format!("manifest_url: {}", pkg.manifest_url));
+                        ui.monospace(format!("required_firmware: {:?}", pkg.required_firmware));
+                        ui.monospace(format!("offset: {}", pkg.offset));
+                        ui.monospace(format!("part_number: {:?}", pkg.part_number));
+                    })
+                ;
+            }
+
             ui.separator();
     
             ui.horizontal(| ui | {
                 let download_status = self.pkg_download_status(title_id, pkg);
 
                 let download_enabled = match download_status {
-                    ActiveDownloadStatus::Downloading(_) | ActiveDownloadStatus::Verifying => false,
+                    ActiveDownloadStatus::Queued | ActiveDownloadStatus::Downloading { .. } | ActiveDownloadStatus::Verifying | ActiveDownloadStatus::Retrying { .. } => false,
                     _ => true
                 };
                 let download_btn = ui.add_enabled(download_enabled, egui::Button::new("Download file"));
                 match download_status {
                     ActiveDownloadStatus::NotStarted => {},
+                    ActiveDownloadStatus::Queued => {
+                        ui.label(egui::RichText::new("Queued").color(egui::Color32::GRAY));
+                    }
                     ActiveDownloadStatus::Verifying => {
                         ui.label(egui::RichText::new("Verifying download...").color(egui::Rgba::from_rgb(1.0, 1.0, 0.6)));
                     }
-                    ActiveDownloadStatus::Downloading(progress) => {
-                        ui.add(egui::ProgressBar::new(progress).show_percentage());
+                    ActiveDownloadStatus::Downloading { progress, speed_bps, eta_secs } => {
+                        ui.vertical(| ui | {
+                            ui.add(egui::ProgressBar::new(progress).show_percentage());
+                            ui.label(format_speed(speed_bps, eta_secs));
+                        });
+                    }
+                    ActiveDownloadStatus::Retrying { seconds, attempt, max } => {
+                        ui.label(egui::RichText::new(format!("Retrying in {seconds}s (attempt {attempt}/{max})...")).color(egui::Rgba::from_rgb(1.0, 1.0, 0.6)));
                     }
                     ActiveDownloadStatus::Completed => {
                         ui.label(egui::RichText::new("Completed").color(egui::Rgba::from_rgb(0.0, 1.0, 0.0)));
@@ -627,6 +1935,12 @@ impl UpdatesApp {
                     ActiveMergeStatus::Merging(_) => {
                         ui.label(egui::RichText::new("Merging...").color(egui::Rgba::from_rgb(1.0, 1.0, 0.6)));
                     },
+                    ActiveMergeStatus::Verifying => {
+                        ui.label(egui::RichText::new("Verifying...").color(egui::Rgba::from_rgb(1.0, 1.0, 0.6)));
+                    },
+                    ActiveMergeStatus::CleaningUp => {
+                        ui.label(egui::RichText::new("Deleting...").color(egui::Rgba::from_rgb(1.0, 1.0, 0.6)));
+                    },
                 }
 
                 let remaining_space = ui.available_size_before_wrap();
@@ -634,7 +1948,8 @@ impl UpdatesApp {
 
                 if download_btn.clicked() {
                     info!("Downloading update {} for serial {} (individual)", pkg.version, title_id);
-                    self.add_download(self.start_download(title_id.to_string(), title, pkg.clone()));
+                    let download = self.start_download(title_id.to_string(), title, pkg.clone(), Duration::ZERO);
+                    self.add_download(download);
                 }
             });
         });
@@ -642,40 +1957,18 @@ impl UpdatesApp {
 
     fn draw_settings_window(&mut self, ctx: &egui::Context) {
         let mut show_window = self.v.show_settings_window;
-        let mut current_download_path = self.v.modified_settings.pkg_download_path.to_string_lossy().to_string();
 
         // Fixed size avoids a bug that makes the window gradually stretch itself vertically for some reason.
         // See https://github.com/RainbowCookie32/rusty-psn/issues/138
-        egui::Window::new("Settings").id(egui::Id::new("cfg_win")).open(&mut show_window).fixed_size([220.0, 200.0]).show(ctx, | ui | {
-            ui.label("Download Path");
-            ui.horizontal(| ui | {
-                ui.add_enabled_ui(false, | ui | {
-                    ui.text_edit_singleline(&mut current_download_path);
-                });
-
-                if ui.button("Pick folder").clicked() {
-                    if let Some(path) = rfd::FileDialog::new().pick_folder() {
-                        self.v.settings_dirty = true;
-                        self.v.modified_settings.pkg_download_path = path;
-                    }
-                }
-
-                if ui.button("Reset").clicked() {
-                    self.v.settings_dirty = true;
-                    self.v.modified_settings.pkg_download_path = PathBuf::from("/pkgs");
-                }
+        egui::Window::new("Settings").id(egui::Id::new("cfg_win")).open(&mut show_window).fixed_size([320.0, 380.0]).show(ctx, | ui | {
+            egui::ScrollArea::vertical().max_height(330.0).auto_shrink([false, true]).show(ui, | ui | {
+                self.draw_settings_section(ui, "Download Settings", | app, ui | app.draw_download_settings(ui));
+                self.draw_settings_section(ui, "Verification", | app, ui | app.draw_verification_settings(ui));
+                self.draw_settings_section(ui, "Network", | app, ui | app.draw_network_settings(ui));
+                self.draw_settings_section(ui, "Notifications", | app, ui | app.draw_notification_settings(ui));
+                self.draw_settings_section(ui, "Advanced", | app, ui | app.draw_advanced_settings(ui));
             });
 
-            ui.add_space(5.0);
-
-            if ui.checkbox(&mut self.v.modified_settings.show_toasts, "Show in-app toasts").changed() {
-                self.v.settings_dirty = true;
-            }
-
-            if ui.checkbox(&mut self.v.modified_settings.show_notifications, "Show system notifications").changed() {
-                self.v.settings_dirty = true;
-            }
-
             ui.with_layout(egui::Layout::bottom_up(egui::Align::TOP), | ui | {
                 ui.horizontal(| ui | {
                     if ui.button("Save settings").clicked() {
@@ -683,6 +1976,7 @@ impl UpdatesApp {
                         self.v.show_settings_window = false;
 
                         self.settings = self.v.modified_settings.clone();
+                        self.reset_http_clients();
                     }
 
                     if ui.add_enabled(self.v.settings_dirty, egui::Button::new("Discard changes")).clicked() {
@@ -698,6 +1992,7 @@ impl UpdatesApp {
                         
                         self.settings = AppSettings::default();
                         self.v.modified_settings = AppSettings::default();
+                        self.reset_http_clients();
                     }
                 });
 
@@ -710,8 +2005,386 @@ impl UpdatesApp {
         }
     }
 
+    fn draw_settings_section<R>(&mut self, ui: &mut egui::Ui, name: &str, add_contents: impl FnOnce(&mut Self, &mut egui::Ui) -> R) {
+        let open = self.settings.settings_section_open.get(name).copied().unwrap_or(true);
+
+        let response = egui::CollapsingHeader::new(name)
+            .open(Some(open))
+            .show(ui, | ui | add_contents(self, ui))
+        ;
+
+        // Whether a section is open isn't a setting the user has to save, so it's applied right away.
+        if response.header_response.clicked() {
+            self.settings.settings_section_open.insert(name.to_string(), !open);
+            self.v.modified_settings.settings_section_open.insert(name.to_string(), !open);
+        }
+    }
+
+    fn draw_download_settings(&mut self, ui: &mut egui::Ui) {
+        let mut current_download_path = self.v.modified_settings.pkg_download_path.to_string_lossy().to_string();
+
+        ui.label("Download Path");
+        ui.horizontal(| ui | {
+            ui.add_enabled_ui(false, | ui | {
+                ui.text_edit_singleline(&mut current_download_path);
+            });
+
+            if ui.button("Pick folder").clicked() {
+                if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                    self.v.settings_dirty = true;
+                    self.v.modified_settings.pkg_download_path = path;
+                }
+            }
+
+            if ui.button("Reset").clicked() {
+                self.v.settings_dirty = true;
+                self.v.modified_settings.pkg_download_path = PathBuf::from("/pkgs");
+            }
+        });
+
+        ui.add_space(5.0);
+
+        ui.label("Only download versions (eg. 01.0*, BLUS30443:02.00)");
+        if ui.text_edit_singleline(&mut self.v.modified_settings.version_allowlist).changed() {
+            self.v.settings_dirty = true;
+        }
+
+        ui.label("Never download versions");
+        if ui.text_edit_singleline(&mut self.v.modified_settings.version_blocklist).changed() {
+            self.v.settings_dirty = true;
+        }
+
+        ui.add_space(5.0);
+
+        ui.label("Simultaneous downloads");
+        let max_downloads = ui.add(egui::Slider::new(&mut self.v.modified_settings.max_concurrent_downloads, 1..=MAX_CONCURRENT_DOWNLOADS_LIMIT))
+            .on_hover_text("Downloads past this limit wait in the queue until another one finishes. Also limits how many searches run at once.");
+
+        if max_downloads.changed() {
+            self.v.settings_dirty = true;
+        }
+
+        ui.add_space(5.0);
+
+        ui.label("Minimum free space to keep on disk");
+        if ui.add(egui::Slider::new(&mut self.v.modified_settings.min_free_space_gb, 0..=100).suffix(" GB")).changed() {
+            self.v.settings_dirty = true;
+        }
+
+        ui.add_space(5.0);
+
+        ui.label("Merged PS4 pkg name");
+        ui.horizontal(| ui | {
+            let template_input = ui.text_edit_singleline(&mut self.v.modified_settings.merged_name_template)
+                .on_hover_text("Available placeholders: {serial}, {version}, {content_id} and {original} (Sony's name for the parts).");
+
+            if template_input.changed() {
+                self.v.settings_dirty = true;
+            }
+
+            if ui.button("Reset").clicked() {
+                self.v.settings_dirty = true;
+                self.v.modified_settings.merged_name_template = String::from(DEFAULT_MERGED_NAME_TEMPLATE);
+            }
+        });
+
+        ui.add_space(5.0);
+
+        let mut current_scratch_path = self.v.modified_settings.scratch_path
+            .as_ref()
+            .map(| p | p.to_string_lossy().to_string())
+            .unwrap_or_else(|| String::from("Title folder"))
+        ;
+
+        ui.label("Merge scratch folder");
+        ui.horizontal(| ui | {
+            ui.add_enabled_ui(false, | ui | {
+                ui.text_edit_singleline(&mut current_scratch_path);
+            });
+
+            if ui.button("Pick folder").clicked() {
+                if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                    self.v.settings_dirty = true;
+                    self.v.modified_settings.scratch_path = Some(path);
+                }
+            }
+
+            if ui.button("Reset").clicked() {
+                self.v.settings_dirty = true;
+                self.v.modified_settings.scratch_path = None;
+            }
+        });
+
+        let auto_merge_checkbox = ui.checkbox(&mut self.v.modified_settings.auto_merge_after_download, "Merge parts automatically")
+            .on_hover_text("Merges split PS4 updates as soon as all of their parts finish downloading.");
+
+        if auto_merge_checkbox.changed() {
+            self.v.settings_dirty = true;
+        }
+
+        let delete_parts_checkbox = ui.checkbox(&mut self.v.modified_settings.auto_delete_parts_after_merge, "Delete parts after merging")
+            .on_hover_text("Removes the parts of split PS4 updates once the merged pkg is in place, instead of keeping both around.\nParts are kept if the merged pkg couldn't be checked against the manifest's digest.");
+
+        if delete_parts_checkbox.changed() {
+            self.v.settings_dirty = true;
+        }
+    }
+
+    fn draw_verification_settings(&mut self, ui: &mut egui::Ui) {
+        let verify_checkbox = ui.checkbox(&mut self.v.modified_settings.verify_existing_files, "Verify existing files")
+            .on_hover_text("When disabled, existing files with the expected size are assumed to be complete. New downloads are always verified.");
+
+        if verify_checkbox.changed() {
+            self.v.settings_dirty = true;
+        }
+
+        let changeinfo_checkbox = ui.checkbox(&mut self.v.modified_settings.save_changeinfo, "Save patch notes")
+            .on_hover_text("Saves the patch notes of downloaded updates next to their pkgs, when Sony has them.");
+
+        if changeinfo_checkbox.changed() {
+            self.v.settings_dirty = true;
+        }
+
+        let quarantine_checkbox = ui.checkbox(&mut self.v.modified_settings.quarantine_failed_files, "Keep files that fail verification")
+            .on_hover_text("Moves downloads with a hash mismatch to a failed/ folder inside the title's folder, so they can be inspected.");
+
+        if quarantine_checkbox.changed() {
+            self.v.settings_dirty = true;
+        }
+
+        let stop_checkbox = ui.checkbox(&mut self.v.modified_settings.stop_on_first_error, "Stop a title's downloads on the first error")
+            .on_hover_text("When one of a title's downloads fails, the rest of them are cancelled.");
+
+        if stop_checkbox.changed() {
+            self.v.settings_dirty = true;
+        }
+
+        ui.label("SHA-1 implementation:");
+
+        let accelerated = ui.radio_value(&mut self.v.modified_settings.sha1_backend, Sha1Backend::Accelerated, "Hardware-accelerated")
+            .on_hover_text("Uses the CPU's SHA extensions when available.");
+        let smol = ui.radio_value(&mut self.v.modified_settings.sha1_backend, Sha1Backend::Smol, "Portable")
+            .on_hover_text("Pure Rust implementation. The default.");
+
+        if accelerated.changed() || smol.changed() {
+            self.v.settings_dirty = true;
+        }
+    }
+
+    fn draw_notification_settings(&mut self, ui: &mut egui::Ui) {
+        if ui.checkbox(&mut self.v.modified_settings.show_toasts, "Show in-app toasts").changed() {
+            self.v.settings_dirty = true;
+        }
+
+        if ui.checkbox(&mut self.v.modified_settings.show_notifications, "Show system notifications").changed() {
+            self.v.settings_dirty = true;
+        }
+    }
+
+    fn draw_advanced_settings(&mut self, ui: &mut egui::Ui) {
+        let developer_checkbox = ui.checkbox(&mut self.v.modified_settings.developer_mode, "Developer mode")
+            .on_hover_text("Shows the raw values parsed for each package.");
+
+        if developer_checkbox.changed() {
+            self.v.settings_dirty = true;
+        }
+
+        let strict_names_checkbox = ui.checkbox(&mut self.v.modified_settings.strict_file_names, "Windows-compatible folder names")
+            .on_hover_text("Names folders so they stay valid on Windows, even when downloading from another OS to a shared drive.");
+
+        if strict_names_checkbox.changed() {
+            self.v.settings_dirty = true;
+        }
+
+        ui.add_space(5.0);
+
+        ui.label("PSN cookies (name=value; ...)");
+        if ui.text_edit_singleline(&mut self.v.modified_settings.psn_cookies).changed() {
+            self.v.settings_dirty = true;
+        }
+
+        ui.add_space(5.0);
+
+        ui.horizontal(| ui | {
+            if ui.button("Export as TOML").clicked() {
+                self.export_settings_toml();
+            }
+
+            let clear_history = ui.add_enabled(!self.history.completed_downloads.is_empty() || !self.history.failed_downloads.is_empty(), egui::Button::new("Clear history"))
+                .on_hover_text("Forgets which updates were downloaded or failed in previous sessions.");
+
+            if clear_history.clicked() {
+                info!("Clearing the download history");
+                self.history.clear();
+            }
+        });
+    }
+
+    fn draw_network_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(| ui | {
+            ui.label("Download retries");
+
+            let max_retries = ui.add(egui::DragValue::new(&mut self.v.modified_settings.max_retries).range(0..=10))
+                .on_hover_text("How many more times to try a download that drops or fails verification. The wait between attempts doubles each time, up to a minute.");
+
+            if max_retries.changed() {
+                self.v.settings_dirty = true;
+            }
+        });
+
+        ui.add_space(5.0);
+
+        ui.horizontal(| ui | {
+            let mut limit_rate = self.v.modified_settings.max_download_rate_kb_per_sec.is_some();
+            let limit_checkbox = ui.checkbox(&mut limit_rate, "Limit download speed")
+                .on_hover_text("Caps the combined speed of every running download.");
+
+            if limit_checkbox.changed() {
+                self.v.modified_settings.max_download_rate_kb_per_sec = if limit_rate { Some(DEFAULT_MAX_DOWNLOAD_RATE_KB_PER_SEC) } else { None };
+                self.v.settings_dirty = true;
+            }
+
+            if let Some(rate) = self.v.modified_settings.max_download_rate_kb_per_sec.as_mut() {
+                if ui.add(egui::DragValue::new(rate).range(1..=u32::MAX as u64).suffix(" KB/s")).changed() {
+                    self.v.settings_dirty = true;
+                }
+            }
+        });
+
+        ui.add_space(5.0);
+
+        ui.label("Connection timeout");
+        let connect_timeout = ui.add(egui::Slider::new(&mut self.v.modified_settings.connect_timeout_secs, 1..=300).suffix(" s"))
+            .on_hover_text("How long to wait for a connection to Sony's servers to be established.");
+
+        if connect_timeout.changed() {
+            self.v.settings_dirty = true;
+        }
+
+        ui.label("Delay between requests");
+        let request_delay = ui.add(egui::Slider::new(&mut self.v.modified_settings.request_delay_ms, 0..=5000).suffix(" ms"))
+            .on_hover_text("Pause between requests to Sony's servers and between starting downloads, to go easier on them. Some random jitter is added.");
+
+        if request_delay.changed() {
+            self.v.settings_dirty = true;
+        }
+
+        ui.label("Request timeout");
+        let request_timeout = ui.add(egui::Slider::new(&mut self.v.modified_settings.request_timeout_secs, 10..=3600).suffix(" s"))
+            .on_hover_text("How long a search can take, or how long a download can go without receiving any data.");
+
+        if request_timeout.changed() {
+            self.v.settings_dirty = true;
+        }
+
+        ui.add_space(5.0);
+
+        ui.label("Proxy");
+        let mut proxy_url = self.v.modified_settings.proxy_url.clone().unwrap_or_default();
+        let proxy = ui.add(egui::TextEdit::singleline(&mut proxy_url).hint_text("socks5://host:port"))
+            .on_hover_text("Sends every request through this proxy. When empty, the HTTPS_PROXY and ALL_PROXY environment variables are used if set.");
+
+        if proxy.changed() {
+            let proxy_url = proxy_url.trim();
+            self.v.modified_settings.proxy_url = if proxy_url.is_empty() { None } else { Some(proxy_url.to_string()) };
+            self.v.settings_dirty = true;
+        }
+
+        if let Some(Err(e)) = self.v.modified_settings.proxy_url.as_deref().map(validate_proxy_url) {
+            ui.colored_label(ui.visuals().error_fg_color, format!("Invalid proxy URL: {e}"));
+        }
+    }
+
+    fn export_settings_toml(&mut self) {
+        let file_name = self.v.config_toml_path
+            .file_name()
+            .map(| name | name.to_string_lossy().to_string())
+            .unwrap_or_else(|| String::from(DEFAULT_CONFIG_TOML))
+        ;
+
+        let path = match rfd::FileDialog::new().add_filter("TOML", &["toml"]).set_file_name(file_name).save_file() {
+            Some(path) => path,
+            None => return
+        };
+
+        let result = toml::to_string_pretty(&self.v.modified_settings)
+            .map_err(| e | e.to_string())
+            .and_then(| contents | std::fs::write(&path, contents).map_err(| e | e.to_string()))
+        ;
+
+        match result {
+            Ok(()) => {
+                info!("Exported settings to {:?}", path);
+                self.show_notifications(format!("Settings exported to {}", path.to_string_lossy()), ToastLevel::Success);
+            }
+            Err(e) => {
+                error!("Failed to export settings to {:?}: {e}", path);
+                self.show_notifications("Failed to export settings. Check the log for details.", ToastLevel::Error);
+            }
+        }
+    }
+
+    fn draw_download_queue_window(&mut self, ctx: &egui::Context) {
+        let mut show_window = self.v.show_download_queue_window;
+
+        egui::Window::new("Downloads").id(egui::Id::new("downloads_win")).open(&mut show_window).resizable(true).default_size([420.0, 320.0]).show(ctx, | ui | {
+            egui::ScrollArea::vertical().auto_shrink([false; 2]).show(ui, | ui | {
+                ui.strong(format!("Active ({})", self.v.download_queue.len()));
+
+                if self.v.download_queue.is_empty() {
+                    ui.label("No active downloads.");
+                }
+
+                for download in self.v.download_queue.iter() {
+                    ui.group(| ui | {
+                        ui.label(format!("{} v{}", download.title_id, download.pkg_id));
+
+                        ui.horizontal(| ui | {
+                            match download.status() {
+                                ActiveDownloadStatus::Downloading { progress, speed_bps, eta_secs } => {
+                                    ui.add(egui::ProgressBar::new(progress).show_percentage().desired_width(200.0));
+                                    ui.label(format!("{} / {} ({})", ByteSize::b(download.progress), ByteSize::b(download.size), format_speed(speed_bps, eta_secs)));
+                                }
+                                ActiveDownloadStatus::Verifying => {
+                                    ui.label(egui::RichText::new("Verifying download...").color(egui::Rgba::from_rgb(1.0, 1.0, 0.6)));
+                                }
+                                ActiveDownloadStatus::Retrying { seconds, attempt, max } => {
+                                    ui.label(egui::RichText::new(format!("Retrying in {seconds}s (attempt {attempt}/{max})...")).color(egui::Rgba::from_rgb(1.0, 1.0, 0.6)));
+                                }
+                                ActiveDownloadStatus::Queued => {
+                                    ui.label(egui::RichText::new("Queued").color(egui::Color32::GRAY));
+                                }
+                                _ => {
+                                    ui.label("Starting...");
+                                }
+                            }
+                        });
+                    });
+                }
+
+                ui.separator();
+                ui.strong(format!("Completed ({})", self.history.completed_downloads.len()));
+
+                for (title_id, pkg_id) in self.history.completed_downloads.iter().rev() {
+                    ui.label(egui::RichText::new(format!("{title_id} v{pkg_id}")).color(egui::Rgba::from_rgb(0.0, 1.0, 0.0)));
+                }
+
+                ui.separator();
+                ui.strong(format!("Failed ({})", self.history.failed_downloads.len()));
+
+                for (title_id, pkg_id) in self.history.failed_downloads.iter().rev() {
+                    ui.label(egui::RichText::new(format!("{title_id} v{pkg_id}")).color(egui::Rgba::from_rgb(1.0, 0.0, 0.0)));
+                }
+            });
+        });
+
+        if !show_window {
+            self.v.show_download_queue_window = false;
+        }
+    }
+
     fn draw_hash_mismatch_window(&mut self, ctx: &egui::Context) {
-        egui::Window::new("File integrity check failed").collapsible(false).fixed_size([550.0, 100.0]).show(ctx, | ui | {
+        egui::Window::new("File integrity check failed").collapsible(false).resizable(false).default_width(550.0).show(ctx, | ui | {
             ui.vertical_centered(| ui | {
                 ui.label(egui::RichText::new("The integrity check for a downloaded file failed.").color(egui::Color32::YELLOW).heading());
                 ui.label(egui::RichText::new("Considering the file is smaller than expected, it's likely that Sony's servers are being unreliable.").strong());
@@ -720,6 +2393,20 @@ impl UpdatesApp {
                 ui.small("fix your shit already sony, it's been years of unreliable downloads.");
             });
 
+            if let Some((checked, expected, actual)) = self.v.mismatch_hashes.as_ref() {
+                ui.separator();
+
+                egui::Grid::new("mismatch_hashes").num_columns(2).show(ui, | ui | {
+                    ui.label(format!("Expected {checked}:"));
+                    ui.monospace(expected);
+                    ui.end_row();
+
+                    ui.label(format!("Actual {checked}:"));
+                    ui.monospace(actual);
+                    ui.end_row();
+                });
+            }
+
             ui.separator();
 
             ui.vertical_centered(| ui | {
@@ -730,7 +2417,84 @@ impl UpdatesApp {
         });
     }
 
+    fn retry_failed_downloads(&mut self) {
+        let failed_downloads = std::mem::take(&mut self.history.failed_downloads);
+        info!("Retrying {} failed download(s)", failed_downloads.len());
+
+        let mut batch = Vec::new();
+
+        for (title_id, pkg_id) in failed_downloads {
+            let update = match self.v.update_results.iter().find(| u | u.title_id == title_id) {
+                Some(update) => update.clone(),
+                None => {
+                    warn!("Couldn't find search results for failed download {title_id} {pkg_id}, skipping retry");
+                    continue;
+                }
+            };
+
+            if let Some(pkg) = update.packages.iter().find(| pkg | pkg.id() == pkg_id) {
+                // The same entry can be on the failed list more than once, don't queue it twice.
+                let already_queued = batch.iter().any(| (id, _, p): &(String, String, PackageInfo) | *id == title_id && p.id() == pkg_id);
+
+                if self.get_active_download(&title_id, pkg).is_none() && !already_queued {
+                    info!("Retrying download of update {pkg_id} for serial {title_id}");
+                    batch.push((title_id.clone(), update.title(), pkg.clone()));
+                }
+            }
+        }
+
+        self.queue_download_batch(batch);
+    }
+
+    // Queues several downloads at once. If there's no connection, all of them would fail one by one,
+    // so the connection is checked first and a single error is shown instead.
+    fn queue_download_batch(&mut self, batch: Vec<(String, String, PackageInfo)>) {
+        if batch.len() <= 1 {
+            for (title_id, title, pkg) in batch {
+                let download = self.start_download(title_id, title, pkg, Duration::ZERO);
+                self.add_download(download);
+            }
+
+            return;
+        }
+
+        if let Some((_, pending)) = self.v.pending_batch.as_mut() {
+            pending.extend(batch);
+            return;
+        }
+
+        let client = self.search_client();
+        let _guard = self.v.rt.enter();
+
+        let probe = Promise::spawn_async(
+            async move {
+                match client {
+                    Ok(client) => client.is_online().await,
+                    Err(e) => {
+                        error!("Failed to create http client for the connectivity probe: {e}");
+                        false
+                    }
+                }
+            }
+        );
+
+        self.v.pending_batch = Some((probe, batch));
+    }
+
+    fn download_semaphore(&mut self) -> Arc<Semaphore> {
+        let limit = self.settings.max_concurrent_downloads.clamp(1, MAX_CONCURRENT_DOWNLOADS_LIMIT);
+
+        if self.v.download_semaphore.0 != limit {
+            self.v.download_semaphore = (limit, Arc::new(Semaphore::new(limit)));
+        }
+
+        self.v.download_semaphore.1.clone()
+    }
+
     fn add_download(&mut self, download: ActiveDownload) {
+        self.v.batch_download_count += 1;
+        self.v.batch_total_size += download.size;
+
         self.v.download_queue.push(download);
     }
 
@@ -740,6 +2504,16 @@ impl UpdatesApp {
             .find(| d | d.title_id == title_id && d.pkg_id == pkg.id());
     } 
 
+    fn title_has_active_downloads(&self, title_id: &str) -> bool {
+        let pending = self.v.pending_batch
+            .as_ref()
+            .map(| (_, batch) | batch.iter().any(| (id, _, _) | id == title_id))
+            .unwrap_or(false)
+        ;
+
+        pending || self.v.download_queue.iter().any(| d | d.title_id == title_id)
+    }
+
     fn get_active_merge(&self, title_id: &str) -> Option<&ActiveMerge> {
         return self.v.merge_queue
             .iter()
@@ -748,8 +2522,13 @@ impl UpdatesApp {
 
     fn title_merge_status(&self, update: &UpdateInfo) -> ActiveMergeStatus {
         if let Some(active_merge) = self.get_active_merge(&update.title_id) {
-            let progress = active_merge.part_progress as f32 / update.packages.len() as f32;
-            return ActiveMergeStatus::Merging(progress);
+            match active_merge.last_received_status {
+                MergeStatus::Verifying => return ActiveMergeStatus::Verifying,
+                MergeStatus::CleaningUp => return ActiveMergeStatus::CleaningUp,
+                _ => {}
+            }
+
+            return ActiveMergeStatus::Merging(active_merge.progress());
         } else if self.v.completed_merges.iter().any(|id| *id == update.title_id) {
             return ActiveMergeStatus::Merged;
         } else if self.v.failed_merges.iter().any(|id| *id == update.title_id) {
@@ -763,10 +2542,10 @@ impl UpdatesApp {
         let download = match self.get_active_download(title_id, pkg) {
             Some(d) => d,
             None => {
-                if self.v.completed_downloads.iter().any(| (id, pkg_id) | id == title_id && pkg_id == &pkg.id()) {
+                if self.history.completed_downloads.iter().any(| (id, pkg_id) | id == title_id && pkg_id == &pkg.id()) {
                     return ActiveDownloadStatus::Completed
                 }
-                else if self.v.failed_downloads.iter().any(| (id, pkg_id) | id == title_id && pkg_id == &pkg.id()) {
+                else if self.history.failed_downloads.iter().any(| (id, pkg_id) | id == title_id && pkg_id == &pkg.id()) {
                     return ActiveDownloadStatus::Failed
                 }
 
@@ -774,17 +2553,7 @@ impl UpdatesApp {
             }
         };
 
-        match download.last_received_status {
-            DownloadStatus::Progress(_) => {
-                return ActiveDownloadStatus::Downloading(download.progress as f32 / download.size as f32)
-            }
-            DownloadStatus::Verifying => {
-                return ActiveDownloadStatus::Verifying
-            }
-            _ => {
-                return ActiveDownloadStatus::NotStarted
-            }
-        }
+        download.status()
     }
 
     fn pkg_merge_status(&self, title_id: &str, pkg: &PackageInfo) -> ActiveMergeStatus {
@@ -796,7 +2565,11 @@ impl UpdatesApp {
         };
 
         if let Some(active_merge) = self.get_active_merge(title_id) {
-            if active_merge.part_progress < part_number {
+            if let MergeStatus::Verifying = active_merge.last_received_status {
+                return ActiveMergeStatus::Verifying
+            } else if let MergeStatus::CleaningUp = active_merge.last_received_status {
+                return ActiveMergeStatus::CleaningUp
+            } else if active_merge.part_progress < part_number {
                 return ActiveMergeStatus::Merging(0.0)
             } else {
                 return ActiveMergeStatus::Merged
@@ -814,8 +2587,12 @@ impl UpdatesApp {
 #[derive(PartialEq, Debug, Clone, Copy)]
 enum ActiveDownloadStatus {
     NotStarted,
-    Downloading(f32),
+    // Waiting for a free download slot.
+    Queued,
+    Downloading { progress: f32, speed_bps: u64, eta_secs: Option<u64> },
     Verifying,
+    // Seconds left until the failed transfer is tried again.
+    Retrying { seconds: u64, attempt: u32, max: u32 },
     Completed,
     Failed
 }
@@ -825,6 +2602,78 @@ enum ActiveMergeStatus {
     NotMergable,
     NotStarted,
     Merging(f32),
+    // The merged file is being hashed against the manifest's digest.
+    Verifying,
+    // The merge is done, and the parts are being deleted.
+    CleaningUp,
     Merged,
     Failed
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    use poll_promise::Promise;
+
+    use super::{ActiveDownload, ActiveDownloadStatus, AppSettings, DownloadStatus, SETTINGS_KEYS};
+
+    #[test]
+    fn settings_keys_cover_every_field() {
+        let serialized = toml::Value::try_from(AppSettings::default()).unwrap();
+
+        for key in serialized.as_table().unwrap().keys() {
+            assert!(SETTINGS_KEYS.contains(&key.as_str()), "{key} is missing from SETTINGS_KEYS");
+        }
+    }
+
+    #[test]
+    fn merge_toml_sets_unset_options() {
+        let contents = "proxy_url = \"socks5://127.0.0.1:1080\"\nmax_download_rate_kb_per_sec = 500\nnot_a_setting = true";
+        let merged = AppSettings::default().merge_toml(Path::new("rusty-psn.toml"), contents).unwrap();
+
+        assert_eq!(merged.proxy_url.as_deref(), Some("socks5://127.0.0.1:1080"));
+        assert_eq!(merged.max_download_rate_kb_per_sec, Some(500));
+    }
+
+    #[test]
+    fn merge_toml_renamed_settings() {
+        let settings = AppSettings { max_download_rate_kb_per_sec: Some(100), ..Default::default() };
+
+        let merged = settings.merge_toml(Path::new("rusty-psn.toml"), "max_download_rate_kbps = 500").unwrap();
+        assert_eq!(merged.max_download_rate_kb_per_sec, Some(500));
+    }
+
+    #[test]
+    fn retrying_download_counts_down() {
+        let (_tx, rx) = tokio::sync::mpsc::channel(1);
+        let download = ActiveDownload {
+            title_id: String::from("NPUB30826"),
+            pkg_id: String::from("UP0001-NPUB30826_00-0000000000000000"),
+
+            size: 1024,
+            progress: 512,
+            speed_samples: VecDeque::new(),
+            queued: false,
+            last_received_status: DownloadStatus::Retrying { attempt: 2, max: 5, delay: Duration::from_secs(4) },
+            retry_deadline: Some(Instant::now() + Duration::from_millis(2500)),
+
+            promise: Promise::from_ready(Ok(())),
+            progress_rx: rx
+        };
+
+        assert_eq!(download.status(), ActiveDownloadStatus::Retrying { seconds: 3, attempt: 2, max: 5 });
+    }
+
+    #[test]
+    fn pool_settings_from_toml() {
+        let contents = "pool_max_idle_per_host = 4\npool_idle_timeout_secs = 30";
+        let merged = AppSettings::default().merge_toml(Path::new("rusty-psn.toml"), contents).unwrap();
+        let pool = merged.pool_config();
+
+        assert_eq!(pool.max_idle_per_host, Some(4));
+        assert_eq!(pool.idle_timeout, Some(std::time::Duration::from_secs(30)));
+    }
+}
@@ -1,14 +1,13 @@
 // On release builds, this hides the console window that's created on Windows.
 #![cfg_attr(all(not(debug_assertions), feature = "egui"), windows_subsystem = "windows")]
 
-#[cfg(feature = "cli")]
+#[cfg(any(feature = "cli", feature = "egui"))]
 use std::path::PathBuf;
 use flexi_logger::Logger;
 use clap::Parser;
 
 #[macro_use] extern crate log;
-mod psn;
-mod utils;
+use libpsn::{psn, utils};
 #[cfg(feature = "cli")]
 mod cli;
 #[cfg(feature = "egui")]
@@ -18,22 +17,132 @@ mod egui;
 #[clap(author, version, about)]
 struct Args {
     #[cfg(feature = "cli")]
-    #[clap(short, long, required = true, help = "The serial(s) you want to search for, in quotes and separated by spaces")]
+    #[clap(short, long, required_unless_present_any = ["status", "from_manifest", "titles_file"], help = "The serial(s) you want to search for, in quotes and separated by spaces")]
     titles: Vec<String>,
     #[cfg(feature = "cli")]
+    #[clap(long, conflicts_with = "from_manifest", help = "A file with more serials to search for, one per line. Empty lines and lines starting with # are ignored.")]
+    titles_file: Option<PathBuf>,
+    #[cfg(feature = "cli")]
     #[clap(short, long, help = "Downloads all available updates printing only errors, without needing user intervention.")]
     silent: bool,
     #[cfg(feature = "cli")]
-    #[clap(short, long, help = "Target folder to save the downloaded update files to.")]
+    #[clap(short, long, help = "Target folder to save the downloaded update files to. Defaults to $RUSTY_PSN_DOWNLOAD_DIR, or pkgs/ if it isn't set.")]
     destination_path: Option<PathBuf>,
+    #[cfg(feature = "cli")]
+    #[clap(long, default_value_t = 50, help = "Maximum amount of serials to process in one go. Going over it needs confirmation, or --yes.")]
+    max_titles: usize,
+    #[cfg(feature = "cli")]
+    #[clap(short, long, help = "Process more serials than --max-titles allows without asking for confirmation.")]
+    yes: bool,
+    #[cfg(feature = "cli")]
+    #[clap(short, long, value_enum, default_value_t = cli::OutputFormat::Text, help = "Output format. json and csv print a report of the found updates and how their downloads went once done, and imply --silent.")]
+    format: cli::OutputFormat,
+    #[cfg(feature = "cli")]
+    #[clap(long, conflicts_with = "format", help = "Shorthand for --format json.")]
+    json: bool,
+    #[cfg(feature = "cli")]
+    #[clap(short = 'n', long, help = "Show where each update would be saved, and whether it's already there, without downloading anything.")]
+    dry_run: bool,
+    #[cfg(feature = "cli")]
+    #[clap(long, help = "Merge the parts of split PS4 updates into a single pkg once they're downloaded, without asking.")]
+    merge: bool,
+    #[cfg(feature = "cli")]
+    #[clap(long, help = "Check the files left by a previous run first, skipping the ones that are complete and picking up partial ones where they stopped.")]
+    resume: bool,
+    #[cfg(feature = "cli")]
+    #[clap(long, help = "Instead of downloading, hash the already downloaded files of the updates. Exits with 5 if any is wrong, or 6 if any is missing.")]
+    verify_only: bool,
+    #[cfg(feature = "cli")]
+    #[clap(long, help = "Instead of downloading, verify the pkgs in this folder against the updates for the given serial(s).")]
+    verify_folder: Option<PathBuf>,
+    #[cfg(feature = "cli")]
+    #[clap(long, help = "Save the updates that were found to this JSON file before downloading, to check on them later with --status.")]
+    save_plan: Option<PathBuf>,
+    #[cfg(feature = "cli")]
+    #[clap(long, help = "Write the URL, version, hash and size of every update that was found to this JSON file before downloading. Works with --dry-run.")]
+    output_manifest: Option<PathBuf>,
+    #[cfg(feature = "cli")]
+    #[clap(long, conflicts_with = "titles", help = "Download the updates listed in a JSON file written by --output-manifest, instead of searching for them.")]
+    from_manifest: Option<PathBuf>,
+    #[cfg(feature = "cli")]
+    #[clap(long, help = "Report which files of a plan saved with --save-plan are complete, partial or missing, without downloading anything.")]
+    status: Option<PathBuf>,
+    #[cfg(feature = "cli")]
+    #[clap(long, help = "Seconds to wait after each title's downloads. Defaults to 3, or 0 in silent mode or when not running in a terminal.")]
+    pause_between: Option<u64>,
+    #[cfg(feature = "cli")]
+    #[clap(long, default_value_t = 0, help = "Free space (in GB) that downloads have to leave on the target disk.")]
+    min_free_space: u64,
+    #[cfg(feature = "cli")]
+    #[clap(long, help = "Save the patch notes of each downloaded update next to its pkg, when Sony has them.")]
+    save_changeinfo: bool,
+    #[cfg(feature = "cli")]
+    #[clap(long, help = "Name folders so they're valid on Windows even on other systems, eg. for network shares.")]
+    strict_file_names: bool,
+    #[cfg(feature = "cli")]
+    #[clap(long, default_value_t = psn::DEFAULT_DOWNLOAD_RETRIES, help = "How many more times to try a download that drops or fails verification, waiting longer after each attempt.")]
+    max_retries: u32,
+    #[cfg(feature = "cli")]
+    #[clap(long, help = "Move downloads that fail verification to a failed/ folder inside the title's folder, to inspect them later.")]
+    quarantine_failed: bool,
+    #[cfg(feature = "cli")]
+    #[clap(long, help = "Skip the remaining updates of a title once one of its downloads fails.")]
+    stop_on_first_error: bool,
+    #[cfg(feature = "cli")]
+    #[clap(long, help = "Assume existing files with the expected size are complete instead of hashing them. New downloads are still verified.")]
+    no_verify_existing: bool,
+    #[cfg(feature = "cli")]
+    #[clap(long, value_enum, ignore_case = true, help = "Only download the updates of titles for this platform.")]
+    filter_platform: Option<psn::utils::PlaformVariant>,
+    #[cfg(feature = "cli")]
+    #[clap(long, value_enum, default_value_t = utils::Sha1Backend::Smol, help = "SHA-1 implementation used to verify downloads.")]
+    sha1_backend: utils::Sha1Backend,
+    #[cfg(feature = "cli")]
+    #[clap(long, default_value_t = psn::client::DEFAULT_CONNECT_TIMEOUT_SECS, help = "Seconds to wait for a connection to be established.")]
+    connect_timeout: u64,
+    #[cfg(feature = "cli")]
+    #[clap(long, default_value_t = psn::client::DEFAULT_REQUEST_TIMEOUT_SECS, help = "Seconds a search can take, or a download can go without receiving data.")]
+    request_timeout: u64,
+    #[cfg(feature = "cli")]
+    #[clap(long, default_value_t = 0, help = "Milliseconds to wait between requests to Sony's servers and between downloads, plus some random jitter.")]
+    request_delay_ms: u64,
+    #[cfg(feature = "cli")]
+    #[clap(long, value_name = "KB/s", value_parser = clap::value_parser!(u64).range(1..), help = "Limit the combined speed of all downloads, in KB/s (thousands of bytes per second).")]
+    max_rate: Option<u64>,
+    #[cfg(feature = "cli")]
+    #[clap(long, help = "Maximum amount of idle connections to keep open per host, to be reused by later requests.")]
+    pool_max_idle_per_host: Option<usize>,
+    #[cfg(feature = "cli")]
+    #[clap(long, help = "Seconds an idle connection is kept open to be reused. Defaults to 90.")]
+    pool_idle_timeout: Option<u64>,
+    #[cfg(feature = "cli")]
+    #[clap(long = "allow-version", help = "Only download versions matching this [SERIAL:]VERSION pattern (* is a wildcard). Can be used multiple times.")]
+    version_allowlist: Vec<String>,
+    #[cfg(feature = "cli")]
+    #[clap(long = "block-version", help = "Never download versions matching this [SERIAL:]VERSION pattern (* is a wildcard). Can be used multiple times.")]
+    version_blocklist: Vec<String>,
+    #[cfg(feature = "cli")]
+    #[clap(long, help = "Send every request through this proxy, eg. http://host:port or socks5://host:port. Defaults to $HTTPS_PROXY or $ALL_PROXY if they're set.")]
+    proxy: Option<String>,
+    #[cfg(feature = "cli")]
+    #[clap(long = "cookie", help = "A cookie in name=value form to send to PSN's metadata servers. Can be used multiple times.")]
+    cookies: Vec<String>,
+    #[cfg(feature = "egui")]
+    #[clap(long, help = "Path to a TOML file with settings that take precedence over the stored ones. Defaults to rusty-psn.toml.")]
+    config_toml: Option<PathBuf>,
     #[clap(long, help = "Disables writing the program's log to a .log file. Don't use if you need help.")]
-    no_log_file: bool
+    no_log_file: bool,
+    #[clap(long, help = "Also log every received chunk of data during downloads. Makes the log file grow quite a bit.")]
+    verbose: bool
 }
 
 fn main() {
     let args = Args::parse();
 
-    let mut logger = Logger::try_with_str("info")
+    // Debug level is only raised for our own messages, dependencies are chatty enough at info.
+    let log_spec = if args.verbose { "info, rusty_psn=debug, libpsn=debug" } else { "info" };
+
+    let mut logger = Logger::try_with_str(log_spec)
         .expect("Failed to create logger");
 
     if args.no_log_file {
@@ -47,6 +156,9 @@ fn main() {
         .start()
         .expect("Failed to start logger!");
 
+    #[cfg(feature = "egui")]
+    let config_toml = args.config_toml.clone();
+
     #[cfg(feature = "cli")]
     {
         info!("starting cli app");
@@ -59,8 +171,12 @@ fn main() {
 
         eframe::run_native(
             "rusty-psn",
-            eframe::NativeOptions::default(),
-            Box::new(|cc| Ok(Box::new(egui::UpdatesApp::new(cc))))
+            eframe::NativeOptions {
+                // The window geometry is stored with the rest of the settings, see UpdatesApp::update_window_geometry.
+                persist_window: false,
+                ..Default::default()
+            },
+            Box::new(move |cc| Ok(Box::new(egui::UpdatesApp::new(cc, config_toml))))
         ).expect("Failed to run egui app");
     }
 }